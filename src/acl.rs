@@ -0,0 +1,74 @@
+use crate::callsign::Callsign;
+use crate::cidr::Cidr;
+use serde::{Deserialize, Deserializer};
+use std::net::IpAddr;
+
+/// A callsign or callsign pattern. `*` matches any run of characters
+/// and `?` a single character. Patterns are compared against both the
+/// full callsign and its base, so `N0CALL` also matches `N0CALL-7`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallsignPattern(String);
+
+impl CallsignPattern {
+    pub fn matches(&self, callsign: &Callsign) -> bool {
+        glob(self.0.as_bytes(), callsign.base().as_bytes())
+            || glob(self.0.as_bytes(), callsign.to_string().as_bytes())
+    }
+}
+
+impl<'de> Deserialize<'de> for CallsignPattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        let text = text.trim().to_uppercase();
+        if text.is_empty() {
+            return Err(serde::de::Error::custom("empty callsign pattern"));
+        }
+        Ok(Self(text))
+    }
+}
+
+fn glob(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some((b'*', rest)) => (0..=text.len()).any(|i| glob(rest, &text[i..])),
+        Some((b'?', rest)) => !text.is_empty() && glob(rest, &text[1..]),
+        Some((c, rest)) => text.first() == Some(c) && glob(rest, &text[1..]),
+    }
+}
+
+/// Allow and deny lists for callsigns and client addresses. Deny entries
+/// always win; a non-empty allow list admits only what it matches.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct AclConfig {
+    #[serde(default)]
+    pub allow_callsigns: Vec<CallsignPattern>,
+    #[serde(default)]
+    pub deny_callsigns: Vec<CallsignPattern>,
+    #[serde(default)]
+    pub allow_addresses: Vec<Cidr>,
+    #[serde(default)]
+    pub deny_addresses: Vec<Cidr>,
+}
+
+impl AclConfig {
+    /// Whether `callsign` connecting from `ip` is admitted.
+    pub fn permits(&self, callsign: &Callsign, ip: &IpAddr) -> bool {
+        !self.denies_callsign(callsign)
+            && !self.deny_addresses.iter().any(|net| net.contains(ip))
+            && (self.allow_callsigns.is_empty()
+                || self.allow_callsigns.iter().any(|p| p.matches(callsign)))
+            && (self.allow_addresses.is_empty()
+                || self.allow_addresses.iter().any(|net| net.contains(ip)))
+    }
+
+    /// Whether `callsign` or `ip` is named by an allow list, as required
+    /// on restricted modules.
+    pub fn explicitly_allows(&self, callsign: &Callsign, ip: &IpAddr) -> bool {
+        self.allow_callsigns.iter().any(|p| p.matches(callsign))
+            || self.allow_addresses.iter().any(|net| net.contains(ip))
+    }
+
+    pub fn denies_callsign(&self, callsign: &Callsign) -> bool {
+        self.deny_callsigns.iter().any(|p| p.matches(callsign))
+    }
+}
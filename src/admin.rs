@@ -0,0 +1,242 @@
+use crate::api::SharedState;
+use crate::ban::{Ban, BanTarget};
+use crate::callsign::Callsign;
+use crate::cidr::Cidr;
+use crate::control::send_disc;
+use crate::reflector::Reflector;
+use crate::recorder::Recorder;
+use crate::reload::ReloadRequest;
+use axum::{
+    extract::{Path, Request, State},
+    http::{header, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{delete, get, post},
+    Json, Router,
+};
+use serde::Deserialize;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::net::UdpSocket;
+use tokio::sync::{broadcast, mpsc, oneshot};
+
+#[derive(Clone)]
+pub struct AdminState {
+    pub reflector: SharedState,
+    pub socket: Arc<UdpSocket>,
+    pub tx: broadcast::Sender<String>,
+    pub token: Arc<str>,
+    pub reload: mpsc::Sender<ReloadRequest>,
+}
+
+/// Routes under `/api/v1/admin` plus `/api/v1/recordings`, all requiring
+/// `Authorization: Bearer <admin_token>`.
+pub fn create_router(state: AdminState) -> Router {
+    Router::new()
+        .route("/api/v1/admin/peers/{address}/kick", post(kick_peer))
+        .route(
+            "/api/v1/admin/peers/{address}/mute",
+            post(mute_peer).delete(unmute_peer),
+        )
+        .route("/api/v1/admin/bans", get(list_bans).post(add_ban))
+        .route("/api/v1/admin/bans/{id}", delete(remove_ban))
+        .route("/api/v1/admin/reload", post(reload_config))
+        .route("/api/v1/recordings", get(list_recordings))
+        .route("/api/v1/recordings/{name}", get(get_recording))
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_token))
+        .with_state(state)
+}
+
+async fn require_token(State(state): State<AdminState>, request: Request, next: Next) -> Response {
+    let authorized = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .is_some_and(|token| token_eq(token.trim().as_bytes(), state.token.as_bytes()));
+    if !authorized {
+        return (StatusCode::UNAUTHORIZED, [(header::WWW_AUTHENTICATE, "Bearer")]).into_response();
+    }
+    next.run(request).await
+}
+
+/// Compares tokens without returning early on the first mismatch.
+fn token_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Sends DISC to the peer at `addr` and removes it. Returns false if no
+/// such peer is connected.
+async fn disconnect(r: &mut Reflector, addr: &SocketAddr, socket: &UdpSocket) -> bool {
+    let Some(peer) = r.find_peer(addr) else {
+        return false;
+    };
+    if let Err(e) = send_disc(peer, socket).await {
+        log::error!("Failed to send DISC to {}: {}", addr, e);
+    }
+    log::info!("Disconnected {} ({}) by administrator", peer.callsign, addr);
+    r.remove_peer(addr);
+    true
+}
+
+async fn kick_peer(
+    State(state): State<AdminState>,
+    Path(address): Path<SocketAddr>,
+) -> StatusCode {
+    let mut r = state.reflector.lock().await;
+    if !disconnect(&mut r, &address, &state.socket).await {
+        return StatusCode::NOT_FOUND;
+    }
+    let _ = state.tx.send("update".into());
+    StatusCode::NO_CONTENT
+}
+
+async fn mute_peer(State(state): State<AdminState>, Path(address): Path<SocketAddr>) -> StatusCode {
+    set_muted(&state, address, true).await
+}
+
+async fn unmute_peer(
+    State(state): State<AdminState>,
+    Path(address): Path<SocketAddr>,
+) -> StatusCode {
+    set_muted(&state, address, false).await
+}
+
+async fn set_muted(state: &AdminState, address: SocketAddr, muted: bool) -> StatusCode {
+    let mut r = state.reflector.lock().await;
+    let callsign = if muted {
+        r.mute(address).map(|callsign| callsign.to_string())
+    } else {
+        r.unmute(&address)
+    };
+    let Some(callsign) = callsign else {
+        return StatusCode::NOT_FOUND;
+    };
+    log::info!(
+        "{} ({}) {} by administrator",
+        callsign,
+        address,
+        if muted { "muted" } else { "unmuted" }
+    );
+    let _ = state.tx.send("update".into());
+    StatusCode::NO_CONTENT
+}
+
+async fn list_bans(State(state): State<AdminState>) -> Json<Vec<Ban>> {
+    let mut r = state.reflector.lock().await;
+    r.prune_bans();
+    Json(r.bans.clone())
+}
+
+/// Exactly one of `callsign` and `address` must be given. Bans without
+/// `duration` (in seconds) last until they are removed.
+#[derive(Deserialize)]
+struct BanRequest {
+    callsign: Option<Callsign>,
+    address: Option<Cidr>,
+    duration: Option<u64>,
+    reason: Option<String>,
+}
+
+async fn add_ban(
+    State(state): State<AdminState>,
+    Json(request): Json<BanRequest>,
+) -> Result<(StatusCode, Json<Ban>), (StatusCode, String)> {
+    let target = match (request.callsign, request.address) {
+        (Some(callsign @ Callsign::Normal(_)), None) => BanTarget::Callsign(callsign.base()),
+        (None, Some(address)) => BanTarget::Address(address),
+        (Some(_), None) => {
+            return Err((StatusCode::BAD_REQUEST, "callsign cannot be banned".into()));
+        }
+        _ => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "expected exactly one of callsign and address".into(),
+            ));
+        }
+    };
+    let expires = match request.duration {
+        Some(secs) => match SystemTime::now().checked_add(Duration::from_secs(secs)) {
+            Some(expires) => Some(expires),
+            None => return Err((StatusCode::BAD_REQUEST, "duration is too long".into())),
+        },
+        None => None,
+    };
+
+    let mut r = state.reflector.lock().await;
+    let (ban, covered) = r.add_ban(target, request.reason, expires);
+    for addr in covered {
+        disconnect(&mut r, &addr, &state.socket).await;
+    }
+    let _ = state.tx.send("update".into());
+    Ok((StatusCode::CREATED, Json(ban)))
+}
+
+async fn remove_ban(State(state): State<AdminState>, Path(id): Path<u64>) -> StatusCode {
+    let mut r = state.reflector.lock().await;
+    if r.remove_ban(id) {
+        log::info!("Removed ban {}", id);
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+async fn reload_config(State(state): State<AdminState>) -> Result<StatusCode, (StatusCode, String)> {
+    let (reply, result) = oneshot::channel();
+    // If the reload task is gone the reply sender is dropped and the
+    // receive below fails.
+    let _ = state.reload.send(reply).await;
+    match result.await {
+        Ok(Ok(())) => Ok(StatusCode::NO_CONTENT),
+        Ok(Err(e)) => Err((StatusCode::UNPROCESSABLE_ENTITY, e)),
+        Err(_) => Err((StatusCode::SERVICE_UNAVAILABLE, "reload unavailable".into())),
+    }
+}
+
+async fn list_recordings(State(state): State<AdminState>) -> impl IntoResponse {
+    let directory = {
+        let r = state.reflector.lock().await;
+        let Some(recorder) = r.recorder.as_ref() else {
+            return Err(StatusCode::NOT_FOUND);
+        };
+        recorder.directory().to_path_buf()
+    };
+    match Recorder::list(&directory) {
+        Ok(mut recordings) => {
+            recordings.sort_by_key(|r| std::cmp::Reverse(r.modified));
+            Ok(Json(recordings))
+        }
+        Err(e) => {
+            log::error!("Failed to list recordings: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn get_recording(
+    State(state): State<AdminState>,
+    Path(name): Path<String>,
+) -> impl IntoResponse {
+    let directory = {
+        let r = state.reflector.lock().await;
+        r.recorder.as_ref().map(|recorder| recorder.directory().to_path_buf())
+    };
+    let Some(path) = directory.and_then(|dir| Recorder::path(&dir, &name)) else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+    let data = tokio::fs::read(&path)
+        .await
+        .map_err(|_| StatusCode::NOT_FOUND)?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/octet-stream".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", name),
+            ),
+        ],
+        data,
+    ))
+}
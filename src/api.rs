@@ -17,6 +17,8 @@ pub fn create_router(state: SharedState) -> Router {
         .route("/api/v1/modules", get(get_modules))
         .route("/api/v1/streams/active", get(get_active_streams))
         .route("/api/v1/streams/recent", get(get_recent_streams))
+        .route("/api/v1/positions", get(get_positions))
+        .route("/api/v1/interlinks", get(get_interlinks))
         .with_state(state)
 }
 
@@ -49,3 +51,15 @@ async fn get_recent_streams(State(state): State<SharedState>) -> impl IntoRespon
     let snapshot = guard.export_state().snapshot();
     Json(snapshot.recent_streams)
 }
+
+async fn get_positions(State(state): State<SharedState>) -> impl IntoResponse {
+    let guard = state.lock().await;
+    let snapshot = guard.export_state().snapshot();
+    Json(snapshot.positions)
+}
+
+async fn get_interlinks(State(state): State<SharedState>) -> impl IntoResponse {
+    let guard = state.lock().await;
+    let snapshot = guard.export_state().snapshot();
+    Json(snapshot.interlinks)
+}
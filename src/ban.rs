@@ -0,0 +1,58 @@
+use crate::callsign::Callsign;
+use crate::cidr::Cidr;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::net::IpAddr;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// A callsign or address range refused by an administrator. Bans
+/// without `expires` last until removed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Ban {
+    pub id: u64,
+    #[serde(flatten)]
+    pub target: BanTarget,
+    pub reason: Option<String>,
+    pub created: SystemTime,
+    pub expires: Option<SystemTime>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BanTarget {
+    /// Base callsign, so a ban covers every suffix of the callsign.
+    Callsign(String),
+    Address(Cidr),
+}
+
+impl Ban {
+    pub fn matches(&self, callsign: &Callsign, ip: &IpAddr) -> bool {
+        match &self.target {
+            BanTarget::Callsign(base) => callsign.base() == *base,
+            BanTarget::Address(net) => net.contains(ip),
+        }
+    }
+
+    pub fn expired(&self, now: SystemTime) -> bool {
+        self.expires.is_some_and(|t| t <= now)
+    }
+}
+
+/// Reads bans written by `save`. A missing file holds no bans.
+pub fn load(path: &Path) -> io::Result<Vec<Ban>> {
+    match fs::read(path) {
+        Ok(data) => Ok(serde_json::from_slice(&data)?),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Writes `bans` to `path`, replacing the previous file only once the
+/// new one is complete.
+pub fn save(path: &Path, bans: &[Ban]) -> io::Result<()> {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, serde_json::to_vec_pretty(bans)?)?;
+    fs::rename(&tmp, path)
+}
@@ -1,80 +1,241 @@
-fn char_to_base40(c: char) -> u64 {
+use serde::{Deserialize, Deserializer};
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// First value of the special address range, `40^9`.
+const HASH_BASE: u64 = 0xEE6B28000000;
+/// Last value usable for `#`-prefixed addresses, `40^9 + 40^8 - 1`.
+const HASH_MAX: u64 = 0xF46108FFFFFF;
+const BROADCAST: u64 = 0xFFFFFFFFFFFF;
+
+/// An M17 address as carried in control, stream and packet frames.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Callsign {
+    /// A regular base40 callsign such as `N0CALL-7` or `M17-ABC A`.
+    Normal(Base40),
+    /// The all-ones broadcast address.
+    Broadcast,
+    /// The all-zero address, which is never valid on air.
+    Invalid,
+    /// A `#`-prefixed special address; the `#` is not stored.
+    Hash(Base40),
+    /// A value in the special range that has no assigned meaning.
+    Reserved(u64),
+}
+
+/// Text made only of base40 characters and short enough to encode. It
+/// can only be built by parsing or decoding, so encoding never loses
+/// characters.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct Base40(String);
+
+impl Base40 {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::ops::Deref for Base40 {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Base40 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.0, f)
+    }
+}
+
+impl fmt::Display for Base40 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum CallsignError {
+    #[error("callsign is empty")]
+    Empty,
+    #[error("callsign {0:?} is longer than {1} characters")]
+    TooLong(String, usize),
+    #[error("callsign {0:?} contains unsupported character {1:?}")]
+    InvalidChar(String, char),
+}
+
+fn char_to_base40(c: char) -> Option<u64> {
     match c {
-        'A'..='Z' => (c as u64 - 'A' as u64) + 1,
-        '0'..='9' => (c as u64 - '0' as u64) + 27,
-        '-'       => 37,
-        '/'       => 38,
-        '.'       => 39,
-        _         => 0,
+        ' ' => Some(0),
+        'A'..='Z' => Some((c as u64 - 'A' as u64) + 1),
+        '0'..='9' => Some((c as u64 - '0' as u64) + 27),
+        '-' => Some(37),
+        '/' => Some(38),
+        '.' => Some(39),
+        _ => None,
     }
 }
 
 fn base40_to_char(val: u64) -> char {
     match val {
-        1..=26  => (('A' as u8) + (val as u8) - 1) as char,
-        27..=36 => (('0' as u8) + (val as u8) - 27) as char,
-        37      => '-',
-        38      => '/',
-        39      => '.',
-        _       => ' ',
+        1..=26 => (b'A' + (val as u8) - 1) as char,
+        27..=36 => (b'0' + (val as u8) - 27) as char,
+        37 => '-',
+        38 => '/',
+        39 => '.',
+        _ => ' ',
     }
 }
 
-pub fn encode_callsign(callsign: &str) -> [u8; 6] {
+fn base40_encode(text: &str) -> u64 {
     let mut val: u64 = 0;
     let mut pow: u64 = 1;
+    for c in text.chars() {
+        val += char_to_base40(c).unwrap_or(0) * pow;
+        pow *= 40;
+    }
+    val
+}
 
-    let mut chars: Vec<char> = callsign.to_uppercase().chars().collect();
-    if chars.len() > 9 {
-        chars.truncate(9);
+fn base40_decode(mut val: u64, len: usize) -> String {
+    let mut chars = String::with_capacity(len);
+    for _ in 0..len {
+        chars.push(base40_to_char(val % 40));
+        val /= 40;
     }
-    while chars.len() < 9 {
-        chars.push(' ');
+    chars.trim_end().to_string()
+}
+
+fn validate(text: &str, max: usize) -> Result<Base40, CallsignError> {
+    let text = text.trim().to_uppercase();
+    if text.is_empty() {
+        return Err(CallsignError::Empty);
     }
+    if text.chars().count() > max {
+        return Err(CallsignError::TooLong(text, max));
+    }
+    if let Some(c) = text.chars().find(|c| char_to_base40(*c).is_none()) {
+        return Err(CallsignError::InvalidChar(text, c));
+    }
+    Ok(Base40(text))
+}
 
-    for &c in chars.iter() {
-        val += char_to_base40(c) * pow;
-        pow *= 40;
+impl Callsign {
+    pub fn from_bytes(data: &[u8; 6]) -> Self {
+        let mut val: u64 = 0;
+        for b in data.iter() {
+            val = (val << 8) | (*b as u64);
+        }
+
+        match val {
+            0 => Callsign::Invalid,
+            BROADCAST => Callsign::Broadcast,
+            HASH_BASE..=HASH_MAX => Callsign::Hash(Base40(base40_decode(val - HASH_BASE, 8))),
+            _ if val > HASH_MAX => Callsign::Reserved(val),
+            _ => Callsign::Normal(Base40(base40_decode(val, 9))),
+        }
     }
 
-    let mut out = [0u8; 6];
-    for i in 0..6 {
-        out[5 - i] = ((val >> (8 * i)) & 0xFF) as u8;
+    pub fn to_bytes(&self) -> [u8; 6] {
+        let val = match self {
+            Callsign::Normal(text) => base40_encode(text),
+            Callsign::Broadcast => BROADCAST,
+            Callsign::Invalid => 0,
+            Callsign::Hash(text) => HASH_BASE + base40_encode(text),
+            Callsign::Reserved(val) => *val,
+        };
+        val.to_be_bytes()[2..].try_into().unwrap()
     }
-    out
-}
 
-pub fn decode_callsign(data: &[u8; 6]) -> String {
-    let mut val: u64 = 0;
-    for b in data.iter() {
-        val = (val << 8) | (*b as u64);
+    /// Whether the address may appear as the source of a transmission.
+    pub fn is_valid_source(&self) -> bool {
+        matches!(self, Callsign::Normal(_))
     }
 
-    match val {
-        0x000000000000 => return "INVALID".to_string(),
-        0xFFFFFFFFFFFF => return "BROADCAST".to_string(),
-        0xEE6B28000000..=0xFFFFFFFFFFFE => {
-            return format!("RESERVED-{:012X}", val);
+    /// Whether this is a reflector designator such as `M17-ABC`, as sent
+    /// by reflectors that link to us.
+    pub fn is_reflector(&self) -> bool {
+        matches!(self, Callsign::Normal(text) if text.starts_with("M17-"))
+    }
+
+    /// The callsign without any suffix, e.g. `N0CALL` for `N0CALL-7`.
+    pub fn base(&self) -> String {
+        match self {
+            Callsign::Normal(text) => text
+                .split([' ', '-', '/'])
+                .next()
+                .unwrap_or("")
+                .to_string(),
+            other => other.to_string(),
         }
-        _ => {}
     }
 
-    let mut chars = Vec::new();
-    let mut temp = val;
-    for _ in 0..9 {
-        let digit = temp % 40;
-        temp /= 40;
-        chars.push(base40_to_char(digit));
+    /// The suffix following the first separator, e.g. `7` for `N0CALL-7`.
+    pub fn suffix(&self) -> Option<&str> {
+        match self {
+            Callsign::Normal(text) => text
+                .split_once([' ', '-', '/'])
+                .map(|(_, suffix)| suffix.trim())
+                .filter(|suffix| !suffix.is_empty()),
+            _ => None,
+        }
     }
+}
 
-    chars.into_iter().collect::<String>()
+impl FromStr for Callsign {
+    type Err = CallsignError;
+
+    /// Parses a callsign as written by a user. `BROADCAST` maps to the
+    /// broadcast address and a leading `#` to a special address.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.eq_ignore_ascii_case("BROADCAST") {
+            return Ok(Callsign::Broadcast);
+        }
+        match s.strip_prefix('#') {
+            Some(rest) => Ok(Callsign::Hash(validate(rest, 8)?)),
+            None => Ok(Callsign::Normal(validate(s, 9)?)),
+        }
+    }
 }
 
-pub fn base_callsign(callsign: &str) -> String {
-    let trimmed = callsign.trim();
-    trimmed
-        .split(|c| c == ' ' || c == '-' || c == '/')
-        .next()
-        .unwrap_or("")
-        .to_uppercase()
+impl fmt::Display for Callsign {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Callsign::Normal(text) => write!(f, "{}", text),
+            Callsign::Broadcast => write!(f, "BROADCAST"),
+            Callsign::Invalid => write!(f, "INVALID"),
+            Callsign::Hash(text) => write!(f, "#{}", text),
+            Callsign::Reserved(val) => write!(f, "RESERVED-{:012X}", val),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Callsign {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        text.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_that_cannot_be_encoded_is_rejected() {
+        assert!(matches!("N0CALL-1234".parse::<Callsign>(), Err(CallsignError::TooLong(..))));
+        assert!(matches!("#TOOLONG12".parse::<Callsign>(), Err(CallsignError::TooLong(..))));
+        assert!(matches!("N0_CALL".parse::<Callsign>(), Err(CallsignError::InvalidChar(_, '_'))));
+    }
+
+    #[test]
+    fn parsed_callsigns_round_trip() {
+        for text in ["N0CALL", "M17-ABC A", "AB1CD/P", "#ALL", "BROADCAST"] {
+            let callsign: Callsign = text.parse().unwrap();
+            assert_eq!(Callsign::from_bytes(&callsign.to_bytes()), callsign);
+        }
+    }
 }
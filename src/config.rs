@@ -1,27 +1,505 @@
-use serde::Deserialize;
+use crate::acl::AclConfig;
+use crate::callsign::{Callsign, CallsignError};
+use crate::cidr::Cidr;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
 use std::fs;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use thiserror::Error;
 
 #[derive(Debug, Deserialize)]
 pub struct Config {
     pub reflector_name: String,
     pub bind_address: String,
-    pub modules: Vec<char>,
+    pub modules: Vec<ModuleConfig>,
     pub strict_crc: bool,
     #[serde(default)]
     pub interlinks: Vec<InterlinkConfig>,
+    #[serde(default = "default_position_max_age")]
+    pub position_max_age: u64,
+    #[serde(default)]
+    pub peer_reflectors: Vec<PeerReflectorConfig>,
+    #[serde(default)]
+    pub link_transit: bool,
+    #[serde(default = "default_loop_window")]
+    pub loop_window: u64,
+    #[serde(default = "default_interlink_resolve_interval")]
+    pub interlink_resolve_interval: u64,
+    #[serde(default)]
+    pub parrot: Option<ParrotConfig>,
+    #[serde(default)]
+    pub recording: Option<RecordingConfig>,
+    #[serde(default)]
+    pub acl: AclConfig,
+    #[serde(default)]
+    pub module_acls: HashMap<char, AclConfig>,
+    /// Bearer token for `/api/v1/admin`; the admin API is disabled when unset.
+    #[serde(default)]
+    pub admin_token: Option<Secret>,
+    /// File that keeps bans across restarts; bans are lost on restart
+    /// when unset.
+    #[serde(default)]
+    pub ban_file: Option<PathBuf>,
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+    #[serde(default)]
+    pub client_limits: ClientLimits,
+    /// Answer PING and DISC from addresses that are not connected.
+    #[serde(default)]
+    pub reply_to_unknown: bool,
+    /// Seconds HTTP clients get to disconnect on shutdown.
+    #[serde(default = "default_shutdown_grace")]
+    pub shutdown_grace: u64,
+}
+
+/// A string kept out of `Debug` output so it never ends up in the logs.
+#[derive(Clone, Deserialize, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("\"***\"")
+    }
+}
+
+fn default_position_max_age() -> u64 {
+    3600
+}
+
+fn default_loop_window() -> u64 {
+    5
+}
+
+fn default_interlink_resolve_interval() -> u64 {
+    300
+}
+
+fn default_shutdown_grace() -> u64 {
+    5
+}
+
+/// A module, written either as its letter (`"A"`) or as a table such as
+/// `{ name = "A", description = "Local chat", access = "listen_only" }`.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(from = "RawModuleConfig")]
+pub struct ModuleConfig {
+    pub name: char,
+    pub description: String,
+    pub access: AccessMode,
+    /// Longest transmission in seconds before further frames are dropped.
+    pub talk_timeout: Option<u64>,
+    /// Overrides `client_limits.max_module_clients` for this module.
+    pub max_clients: Option<usize>,
+    /// Whether interlinks may attach to this module.
+    pub linked: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum AccessMode {
+    /// Anyone permitted by the access lists may connect and talk.
+    #[default]
+    Open,
+    /// Clients are registered as listeners, even when they send CONN.
+    ListenOnly,
+    /// Only clients matching an allow list in `module_acls` may connect.
+    Restricted,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawModuleConfig {
+    Name(char),
+    Table {
+        name: char,
+        #[serde(default)]
+        description: String,
+        #[serde(default)]
+        access: AccessMode,
+        #[serde(default)]
+        talk_timeout: Option<u64>,
+        #[serde(default)]
+        max_clients: Option<usize>,
+        #[serde(default = "default_module_linked")]
+        linked: bool,
+    },
+}
+
+fn default_module_linked() -> bool {
+    true
+}
+
+impl From<RawModuleConfig> for ModuleConfig {
+    fn from(raw: RawModuleConfig) -> Self {
+        match raw {
+            RawModuleConfig::Name(name) => Self {
+                name,
+                description: String::new(),
+                access: AccessMode::Open,
+                talk_timeout: None,
+                max_clients: None,
+                linked: true,
+            },
+            RawModuleConfig::Table {
+                name,
+                description,
+                access,
+                talk_timeout,
+                max_clients,
+                linked,
+            } => Self {
+                name,
+                description,
+                access,
+                talk_timeout,
+                max_clients,
+                linked,
+            },
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct InterlinkConfig {
     pub name: String,
+    /// `host:port` of the remote reflector; hostnames are resolved at
+    /// startup and again every `interlink_resolve_interval` seconds.
     pub address: String,
+    pub modules: Vec<ModuleMapping>,
+}
+
+/// A remote reflector allowed to link to us. Its `CONN` must come from
+/// one of `addresses`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct PeerReflectorConfig {
+    pub callsign: Callsign,
+    pub addresses: Vec<Cidr>,
+}
+
+impl PeerReflectorConfig {
+    pub fn matches(&self, from: &Callsign, ip: &IpAddr) -> bool {
+        let designator = from.to_string();
+        designator.split(' ').next() == Some(self.callsign.to_string().as_str())
+            && self.addresses.iter().any(|net| net.contains(ip))
+    }
+}
+
+/// Pairs a local module with the module it is linked to on the remote
+/// reflector. Written as `"A"` when both letters match, or as `"A:C"` or
+/// `{ local = "A", remote = "C" }` to link local `A` with remote `C`.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "RawModuleMapping")]
+pub struct ModuleMapping {
+    pub local: char,
+    pub remote: char,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawModuleMapping {
+    Text(String),
+    Table { local: char, remote: char },
+}
+
+impl TryFrom<RawModuleMapping> for ModuleMapping {
+    type Error = String;
+
+    fn try_from(raw: RawModuleMapping) -> Result<Self, Self::Error> {
+        match raw {
+            RawModuleMapping::Table { local, remote } => Ok(Self { local, remote }),
+            RawModuleMapping::Text(text) => {
+                let mut chars = text.chars();
+                match (chars.next(), chars.next(), chars.next(), chars.next()) {
+                    (Some(local), None, None, None) => Ok(Self {
+                        local,
+                        remote: local,
+                    }),
+                    (Some(local), Some(':'), Some(remote), None) => Ok(Self { local, remote }),
+                    _ => Err(format!(
+                        "invalid module mapping {:?}, expected \"A\" or \"A:C\"",
+                        text
+                    )),
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ParrotConfig {
+    #[serde(default = "default_parrot_destination")]
+    pub destination: Callsign,
+    #[serde(default)]
+    pub module: Option<char>,
+    #[serde(default = "default_parrot_max_seconds")]
+    pub max_seconds: u64,
+}
+
+fn default_parrot_destination() -> Callsign {
+    "ECHO".parse().expect("ECHO is a valid callsign")
+}
+
+fn default_parrot_max_seconds() -> u64 {
+    60
+}
+
+impl ParrotConfig {
+    /// Whether a stream to `dst` on `module` should be echoed back. The
+    /// destination is reachable both as written and as its `#` special
+    /// address form, so `ECHO` also answers `#ECHO`.
+    pub fn handles(&self, dst: &Callsign, module: char) -> bool {
+        let same_service = match (dst, &self.destination) {
+            (
+                Callsign::Normal(a) | Callsign::Hash(a),
+                Callsign::Normal(b) | Callsign::Hash(b),
+            ) => a == b,
+            (a, b) => a == b,
+        };
+        same_service || self.module == Some(module)
+    }
+
+    pub fn max_frames(&self) -> usize {
+        (self.max_seconds * 25) as usize
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct RecordingConfig {
+    pub directory: PathBuf,
+    #[serde(default)]
     pub modules: Vec<char>,
+    #[serde(default = "default_recording_max_age")]
+    pub max_age: u64,
+    #[serde(default = "default_recording_max_size")]
+    pub max_size: u64,
+}
+
+fn default_recording_max_age() -> u64 {
+    7 * 24 * 3600
+}
+
+fn default_recording_max_size() -> u64 {
+    1024 * 1024 * 1024
+}
+
+/// Caps on connected clients. Interlinks are not counted; unset limits
+/// are unlimited.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct ClientLimits {
+    /// Clients on the whole reflector.
+    pub max_clients: Option<usize>,
+    /// Clients on any one module.
+    pub max_module_clients: Option<usize>,
+    /// Listen-only clients on any one module.
+    pub max_listen_clients: Option<usize>,
+}
+
+/// Packet rates are per second. Addresses that have `block_threshold`
+/// packets dropped within ten seconds are ignored for `block_seconds`.
+/// At most `max_sources` addresses are tracked; packets from further
+/// addresses are only subject to the global limit.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    pub enabled: bool,
+    pub control_rate: f64,
+    pub control_burst: f64,
+    pub stream_rate: f64,
+    pub stream_burst: f64,
+    pub global_rate: f64,
+    pub global_burst: f64,
+    pub block_threshold: u32,
+    pub block_seconds: u64,
+    pub max_sources: usize,
+    /// Addresses never limited, such as trusted interlinks.
+    pub exempt: Vec<Cidr>,
+}
+
+/// Longest allowed `parrot.max_seconds`, one hour.
+pub const MAX_PARROT_SECONDS: u64 = 3600;
+
+/// Longest allowed `rate_limit.block_seconds`, one year.
+pub const MAX_BLOCK_SECONDS: u64 = 365 * 24 * 3600;
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            control_rate: 10.0,
+            control_burst: 20.0,
+            stream_rate: 100.0,
+            stream_burst: 200.0,
+            global_rate: 5000.0,
+            global_burst: 10000.0,
+            block_threshold: 200,
+            block_seconds: 300,
+            max_sources: 10000,
+            exempt: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("no modules configured")]
+    NoModules,
+    #[error("invalid module name {0:?}, expected a letter A-Z")]
+    InvalidModule(char),
+    #[error("module {0} is configured more than once")]
+    DuplicateModule(char),
+    #[error("{0} refers to unknown module {1}")]
+    UnknownModule(String, char),
+    #[error("invalid callsign {0:?}: {1}")]
+    InvalidCallsign(String, CallsignError),
+    #[error("interlink address {0} links module {1} more than once")]
+    DuplicateInterlinkModule(String, char),
+    #[error("{0} must be at most {1}")]
+    OutOfRange(&'static str, u64),
+    #[error("{0} must be greater than zero")]
+    Zero(&'static str),
 }
 
 impl Config {
+    /// Checks settings that deserialization cannot, such as references
+    /// to modules that are not configured.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.modules.is_empty() {
+            return Err(ConfigError::NoModules);
+        }
+        let mut names = Vec::new();
+        for module in &self.modules {
+            if !module.name.is_ascii_uppercase() {
+                return Err(ConfigError::InvalidModule(module.name));
+            }
+            if names.contains(&module.name) {
+                return Err(ConfigError::DuplicateModule(module.name));
+            }
+            names.push(module.name);
+        }
+        let check_module = |context: &str, module: char| {
+            if names.contains(&module) {
+                Ok(())
+            } else {
+                Err(ConfigError::UnknownModule(context.to_string(), module))
+            }
+        };
+
+        if self.rate_limit.block_seconds > MAX_BLOCK_SECONDS {
+            return Err(ConfigError::OutOfRange(
+                "rate_limit.block_seconds",
+                MAX_BLOCK_SECONDS,
+            ));
+        }
+
+        if self.interlink_resolve_interval == 0 {
+            return Err(ConfigError::Zero("interlink_resolve_interval"));
+        }
+
+        self.reflector_name
+            .parse::<Callsign>()
+            .map_err(|e| ConfigError::InvalidCallsign(self.reflector_name.clone(), e))?;
+        // Frames on a link name their module, so each module may appear
+        // only once on either side of an address.
+        let mut locals = Vec::new();
+        let mut remotes = Vec::new();
+        for link in &self.interlinks {
+            link.name
+                .parse::<Callsign>()
+                .map_err(|e| ConfigError::InvalidCallsign(link.name.clone(), e))?;
+            for mapping in &link.modules {
+                let local = (link.address.as_str(), mapping.local);
+                let remote = (link.address.as_str(), mapping.remote);
+                if locals.contains(&local) {
+                    return Err(ConfigError::DuplicateInterlinkModule(
+                        link.address.clone(),
+                        mapping.local,
+                    ));
+                }
+                if remotes.contains(&remote) {
+                    return Err(ConfigError::DuplicateInterlinkModule(
+                        link.address.clone(),
+                        mapping.remote,
+                    ));
+                }
+                locals.push(local);
+                remotes.push(remote);
+                check_module(&format!("interlink {}", link.name), mapping.local)?;
+                if !mapping.remote.is_ascii_uppercase() {
+                    return Err(ConfigError::InvalidModule(mapping.remote));
+                }
+            }
+        }
+        if self
+            .parrot
+            .as_ref()
+            .is_some_and(|p| p.max_seconds > MAX_PARROT_SECONDS)
+        {
+            return Err(ConfigError::OutOfRange("parrot.max_seconds", MAX_PARROT_SECONDS));
+        }
+        if let Some(module) = self.parrot.as_ref().and_then(|p| p.module) {
+            check_module("parrot", module)?;
+        }
+        if let Some(recording) = &self.recording {
+            for module in &recording.modules {
+                check_module("recording", *module)?;
+            }
+        }
+        for module in self.module_acls.keys() {
+            check_module("module_acls", *module)?;
+        }
+        Ok(())
+    }
+
+    /// Whether `callsign` from `ip` may connect to `module`, checking the
+    /// global lists first and then those of the module.
+    pub fn access_permitted(&self, module: char, callsign: &Callsign, ip: &IpAddr) -> bool {
+        self.acl.permits(callsign, ip)
+            && self
+                .module_acls
+                .get(&module)
+                .is_none_or(|acl| acl.permits(callsign, ip))
+    }
+
+    /// Whether a client `callsign` from `ip` may use `module` with the
+    /// given access mode: it must pass the access lists and, on restricted
+    /// modules, be named by an allow list of the module.
+    pub fn client_permitted(
+        &self,
+        module: char,
+        access: AccessMode,
+        callsign: &Callsign,
+        ip: &IpAddr,
+    ) -> bool {
+        self.access_permitted(module, callsign, ip)
+            && (access != AccessMode::Restricted
+                || self
+                    .module_acls
+                    .get(&module)
+                    .is_some_and(|acl| acl.explicitly_allows(callsign, ip)))
+    }
+
+    /// Whether streams from `callsign` are refused on `module`.
+    pub fn callsign_denied(&self, module: char, callsign: &Callsign) -> bool {
+        self.acl.denies_callsign(callsign)
+            || self
+                .module_acls
+                .get(&module)
+                .is_some_and(|acl| acl.denies_callsign(callsign))
+    }
+
     pub fn load_from_file(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
         let contents = fs::read_to_string(path)?;
         let config: Config = toml::from_str(&contents)?;
+        config.validate()?;
         Ok(config)
     }
 }
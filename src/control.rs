@@ -1,9 +1,9 @@
-use crate::callsign::encode_callsign;
+use crate::callsign::Callsign;
+use crate::config::{AccessMode, Config};
 use crate::packet::ControlKind;
 use crate::peer::Peer;
 use crate::reflector::Reflector;
 use std::net::SocketAddr;
-use std::time::Instant;
 use tokio::net::UdpSocket;
 
 pub async fn handle_control_packet(
@@ -11,21 +11,30 @@ pub async fn handle_control_packet(
     addr: SocketAddr,
     reflector: &mut Reflector,
     socket: &UdpSocket,
+    config: &Config,
 ) -> std::io::Result<()> {
     match pkt {
-        ControlKind::Conn { from, module } => {
-            if let Some(m) = reflector.modules.get_mut(&module) {
-                let peer = Peer::new(from.clone(), addr);
-                m.peers.insert(addr, peer);
+        ControlKind::Conn { from, module } | ControlKind::Lstn { from, module }
+            if reflector.is_banned(&from, &addr.ip()) =>
+        {
+            socket.send_to(&ControlKind::Nack.encode(), addr).await?;
+            log::warn!("Banned {} from {} refused on module {}", from, addr, module);
+        }
 
-                let reply = b"ACKN";
-                socket.send_to(reply, addr).await?;
-                log::info!("{} connected to module {} from {}", from, module, addr);
+        ControlKind::Conn { from, module } if from.is_reflector() => {
+            let trusted = config
+                .peer_reflectors
+                .iter()
+                .any(|p| p.matches(&from, &addr.ip()));
+            if trusted && reflector.modules.get(&module).is_some_and(|m| m.linked) {
+                reflector.attach_peer(module, Peer::new_link(from.clone(), addr));
+
+                socket.send_to(&ControlKind::Ackn.encode(), addr).await?;
+                log::info!("Reflector {} linked to module {} from {}", from, module, addr);
             } else {
-                let reply = b"NACK";
-                socket.send_to(reply, addr).await?;
+                socket.send_to(&ControlKind::Nack.encode(), addr).await?;
                 log::warn!(
-                    "{} tried to connect to invalid module {} from {}",
+                    "Refused link from reflector {} to module {} from {}",
                     from,
                     module,
                     addr
@@ -33,62 +42,117 @@ pub async fn handle_control_packet(
             }
         }
 
-        ControlKind::Lstn { from, module } => {
-            if let Some(m) = reflector.modules.get_mut(&module) {
-                let peer = Peer::new_listen(from.clone(), addr);
-                m.peers.insert(addr, peer);
+        ControlKind::Conn { from, module } | ControlKind::Lstn { from, module }
+            if !config.client_permitted(
+                module,
+                reflector.modules.get(&module).map_or(AccessMode::Open, |m| m.access),
+                &from,
+                &addr.ip(),
+            ) =>
+        {
+            socket.send_to(&ControlKind::Nack.encode(), addr).await?;
+            log::warn!("{} from {} denied access to module {}", from, addr, module);
+        }
 
-                let reply = b"ACKN";
-                socket.send_to(reply, addr).await?;
-                log::info!("{} listening on module {} from {}", from, module, addr);
+        ControlKind::Conn { from, module } => {
+            let listen_only = reflector
+                .modules
+                .get(&module)
+                .is_some_and(|m| m.access == AccessMode::ListenOnly);
+            if !reflector.modules.contains_key(&module) {
+                socket.send_to(&ControlKind::Nack.encode(), addr).await?;
+                log::warn!(
+                    "{} tried to connect to invalid module {} from {}",
+                    from,
+                    module,
+                    addr
+                );
+            } else if let Err(reason) =
+                reflector.admit_client(module, &addr, listen_only, &config.client_limits)
+            {
+                socket.send_to(&ControlKind::Nack.encode(), addr).await?;
+                log::warn!("{} refused on module {} from {}: {}", from, module, addr, reason);
+            } else if listen_only {
+                reflector.attach_peer(module, Peer::new_listen(from.clone(), addr));
+
+                socket.send_to(&ControlKind::Ackn.encode(), addr).await?;
+                log::info!(
+                    "{} connected to listen-only module {} from {}",
+                    from,
+                    module,
+                    addr
+                );
             } else {
-                let reply = b"NACK";
-                socket.send_to(reply, addr).await?;
+                reflector.attach_peer(module, Peer::new(from.clone(), addr));
+
+                socket.send_to(&ControlKind::Ackn.encode(), addr).await?;
+                log::info!("{} connected to module {} from {}", from, module, addr);
+            }
+        }
+
+        ControlKind::Lstn { from, module } => {
+            if !reflector.modules.contains_key(&module) {
+                socket.send_to(&ControlKind::Nack.encode(), addr).await?;
                 log::warn!(
                     "{} tried to listen on invalid module {} from {}",
                     from,
                     module,
                     addr
                 );
+            } else if let Err(reason) =
+                reflector.admit_client(module, &addr, true, &config.client_limits)
+            {
+                socket.send_to(&ControlKind::Nack.encode(), addr).await?;
+                log::warn!("{} refused on module {} from {}: {}", from, module, addr, reason);
+            } else {
+                reflector.attach_peer(module, Peer::new_listen(from.clone(), addr));
+
+                socket.send_to(&ControlKind::Ackn.encode(), addr).await?;
+                log::info!("{} listening on module {} from {}", from, module, addr);
             }
         }
 
+        ControlKind::Ping { from } | ControlKind::Disc { from }
+            if !config.reply_to_unknown && reflector.find_peer(&addr).is_none() =>
+        {
+            reflector.ignored_control_packets += 1;
+            log::debug!("Ignored control packet from unregistered {} ({})", from, addr);
+        }
+
         ControlKind::Ping { from } => {
-            let mut reply = Vec::new();
-            reply.extend_from_slice(b"PONG");
-            reply.extend_from_slice(&encode_callsign(&from));
-            socket.send_to(&reply, addr).await?;
+            let reply = ControlKind::Pong { from: from.clone() };
+            socket.send_to(&reply.encode(), addr).await?;
 
-            if let Some(peer) = reflector.find_peer_mut(&addr) {
-                peer.last_seen = Instant::now();
-            }
+            reflector.touch_peer(&addr);
 
             log::debug!("PING from {} ({}) → PONG sent", from, addr);
         }
 
         ControlKind::Pong { from } => {
-            if let Some(peer) = reflector.find_peer_mut(&addr) {
-                peer.last_seen = Instant::now();
-            }
+            reflector.touch_peer(&addr);
             log::debug!("PONG received from {} ({})", from, addr);
         }
 
         ControlKind::Disc { from } => {
-            let mut reply = Vec::new();
-            reply.extend_from_slice(b"DISC");
-            reply.extend_from_slice(&encode_callsign(&from));
-            socket.send_to(&reply, addr).await?;
+            let reply = ControlKind::Disc { from: from.clone() };
+            socket.send_to(&reply.encode(), addr).await?;
 
             reflector.remove_peer(&addr);
             log::info!("{} ({}) disconnected", from, addr);
         }
 
         ControlKind::Ackn => {
-            log::debug!("ACKN received (unexpected as server)");
+            if !reflector.link_acknowledged(&addr) {
+                log::debug!("ACKN received from {} (no pending interlink)", addr);
+            }
         }
 
         ControlKind::Nack => {
-            log::debug!("NACK received (unexpected as server)");
+            if reflector.link_refused(&addr) {
+                log::warn!("Interlink to {} refused by remote reflector", addr);
+            } else {
+                log::debug!("NACK received from {} (no pending interlink)", addr);
+            }
         }
     }
 
@@ -96,31 +160,31 @@ pub async fn handle_control_packet(
 }
 
 pub async fn send_ping(peer: &Peer, socket: &UdpSocket) -> std::io::Result<()> {
-    let mut pkt = Vec::new();
-    pkt.extend_from_slice(b"PING");
-    pkt.extend_from_slice(&encode_callsign(&peer.callsign));
-    socket.send_to(&pkt, peer.address).await?;
+    let pkt = ControlKind::Ping {
+        from: peer.callsign.clone(),
+    };
+    socket.send_to(&pkt.encode(), peer.address).await?;
     Ok(())
 }
 
 pub async fn send_disc(peer: &Peer, socket: &UdpSocket) -> std::io::Result<()> {
-    let mut pkt = Vec::new();
-    pkt.extend_from_slice(b"DISC");
-    pkt.extend_from_slice(&encode_callsign(&peer.callsign));
-    socket.send_to(&pkt, peer.address).await?;
+    let pkt = ControlKind::Disc {
+        from: peer.callsign.clone(),
+    };
+    socket.send_to(&pkt.encode(), peer.address).await?;
     Ok(())
 }
 
 pub async fn send_conn(
-    callsign: &str,
+    callsign: &Callsign,
     module: char,
     addr: SocketAddr,
     socket: &UdpSocket,
 ) -> std::io::Result<()> {
-    let mut pkt = Vec::new();
-    pkt.extend_from_slice(b"CONN");
-    pkt.extend_from_slice(&encode_callsign(callsign));
-    pkt.push(module as u8);
-    socket.send_to(&pkt, addr).await?;
+    let pkt = ControlKind::Conn {
+        from: callsign.clone(),
+        module,
+    };
+    socket.send_to(&pkt.encode(), addr).await?;
     Ok(())
 }
@@ -0,0 +1,68 @@
+use crate::callsign::Callsign;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+/// Identifies a transmission of a source callsign: a stream by its
+/// stream ID, a packet mode frame by its packet CRC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Transmission {
+    Stream(u16),
+    Packet(u16),
+}
+
+/// Remembers which address each stream (source callsign + stream ID) or
+/// packet (source callsign + CRC) first arrived from, so copies reaching
+/// us over another path of an interlinked mesh can be dropped.
+pub struct StreamDedup {
+    window: Duration,
+    entries: HashMap<(Callsign, Transmission), (SocketAddr, Instant)>,
+}
+
+impl StreamDedup {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            entries: HashMap::new(),
+        }
+    }
+
+    pub fn set_window(&mut self, window: Duration) {
+        self.window = window;
+    }
+
+    /// Returns true if the frame may be processed, i.e. the stream is new
+    /// or keeps arriving from the address it was first seen from.
+    pub fn check(&mut self, src: &Callsign, stream_id: u16, addr: SocketAddr) -> bool {
+        self.check_transmission(src, Transmission::Stream(stream_id), addr)
+    }
+
+    /// Like `check`, for a packet mode frame identified by its CRC.
+    pub fn check_packet(&mut self, src: &Callsign, crc: u16, addr: SocketAddr) -> bool {
+        self.check_transmission(src, Transmission::Packet(crc), addr)
+    }
+
+    fn check_transmission(&mut self, src: &Callsign, id: Transmission, addr: SocketAddr) -> bool {
+        let now = Instant::now();
+        let entry = self
+            .entries
+            .entry((src.clone(), id))
+            .or_insert((addr, now));
+        if entry.0 != addr && now.duration_since(entry.1) <= self.window {
+            return false;
+        }
+        *entry = (addr, now);
+        true
+    }
+
+    pub fn prune(&mut self) {
+        let window = self.window;
+        self.entries.retain(|_, (_, seen)| seen.elapsed() <= window);
+    }
+}
+
+impl Default for StreamDedup {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(5))
+    }
+}
@@ -0,0 +1,182 @@
+use crate::callsign::{Callsign, CallsignError};
+use crate::config::{InterlinkConfig, ModuleMapping};
+use log::{info, warn};
+use serde::Serialize;
+use std::io;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Time to wait for ACKN/NACK before a CONN is considered lost.
+pub const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const MIN_BACKOFF: Duration = Duration::from_secs(2);
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkState {
+    /// CONN sent, waiting for the remote reflector to answer.
+    Connecting,
+    /// The remote reflector acknowledged the link.
+    Linked,
+    /// The remote reflector answered with NACK.
+    Refused,
+    /// No answer or the link timed out; waiting to retry.
+    Down,
+}
+
+/// One module linked to a remote reflector.
+///
+/// `host` is the configured `host:port`; `address` is its most recent
+/// resolution and stays `None` until the host resolves.
+pub struct Interlink {
+    pub name: Callsign,
+    pub host: String,
+    pub address: Option<SocketAddr>,
+    pub module: char,
+    pub remote_module: char,
+    pub state: LinkState,
+    pub attempts: u32,
+    pub state_since: SystemTime,
+    pub next_attempt: Instant,
+    pub conn_sent: Option<Instant>,
+}
+
+impl Interlink {
+    pub fn new(
+        name: Callsign,
+        host: String,
+        address: Option<SocketAddr>,
+        mapping: ModuleMapping,
+    ) -> Self {
+        Self {
+            name,
+            host,
+            address,
+            module: mapping.local,
+            remote_module: mapping.remote,
+            state: LinkState::Down,
+            attempts: 0,
+            state_since: SystemTime::now(),
+            next_attempt: Instant::now(),
+            conn_sent: None,
+        }
+    }
+
+    /// Whether both describe the same configured link.
+    pub fn same_link(&self, other: &Interlink) -> bool {
+        self.name == other.name
+            && self.host == other.host
+            && self.module == other.module
+            && self.remote_module == other.remote_module
+    }
+
+    fn set_state(&mut self, state: LinkState) {
+        if self.state != state {
+            info!(
+                "Interlink {} module {} <-> {} ({}): {:?} -> {:?}",
+                self.name, self.module, self.remote_module, self.host, self.state, state
+            );
+            self.state = state;
+            self.state_since = SystemTime::now();
+        }
+    }
+
+    /// Whether a CONN should be sent now.
+    pub fn connect_due(&self, now: Instant) -> bool {
+        self.address.is_some()
+            && matches!(self.state, LinkState::Down | LinkState::Refused)
+            && now >= self.next_attempt
+    }
+
+    pub fn on_conn_sent(&mut self, now: Instant) {
+        self.attempts += 1;
+        self.conn_sent = Some(now);
+        self.set_state(LinkState::Connecting);
+    }
+
+    pub fn on_ackn(&mut self) {
+        self.attempts = 0;
+        self.conn_sent = None;
+        self.set_state(LinkState::Linked);
+    }
+
+    pub fn on_nack(&mut self, now: Instant) {
+        self.conn_sent = None;
+        self.schedule_retry(now);
+        self.set_state(LinkState::Refused);
+    }
+
+    /// Handles a CONN that was never answered.
+    pub fn check_timeout(&mut self, now: Instant) {
+        if self.state == LinkState::Connecting
+            && self
+                .conn_sent
+                .is_some_and(|sent| now.duration_since(sent) > CONNECT_TIMEOUT)
+        {
+            warn!(
+                "Interlink {} module {} ({}) did not answer CONN",
+                self.name, self.module, self.host
+            );
+            self.conn_sent = None;
+            self.schedule_retry(now);
+            self.set_state(LinkState::Down);
+        }
+    }
+
+    /// Handles a link that could not be attached because a client is
+    /// connected from its address, retrying with backoff.
+    pub fn on_address_conflict(&mut self, now: Instant) {
+        self.attempts += 1;
+        self.conn_sent = None;
+        self.schedule_retry(now);
+        self.set_state(LinkState::Down);
+    }
+
+    /// Handles the loss of an established link.
+    pub fn on_lost(&mut self, now: Instant) {
+        self.attempts = 0;
+        self.next_attempt = now;
+        self.set_state(LinkState::Down);
+    }
+
+    fn schedule_retry(&mut self, now: Instant) {
+        let exponent = self.attempts.saturating_sub(1).min(16);
+        let backoff = (MIN_BACKOFF * 2u32.pow(exponent)).min(MAX_BACKOFF);
+        self.next_attempt = now + backoff;
+    }
+}
+
+/// Resolves `host:port` through the system resolver, preferring an
+/// address of the same family as the local socket.
+pub async fn resolve(host: &str, ipv4: bool) -> io::Result<SocketAddr> {
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host(host).await?.collect();
+    addrs
+        .iter()
+        .find(|a| a.is_ipv4() == ipv4)
+        .or_else(|| addrs.first())
+        .copied()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no addresses found"))
+}
+
+/// Builds one `Interlink` per module mapping of `configs`, resolving each
+/// host once. Hosts that fail to resolve are retried by the resolve task.
+pub async fn from_config(
+    configs: &[InterlinkConfig],
+    ipv4: bool,
+) -> Result<Vec<Interlink>, CallsignError> {
+    let mut links = Vec::new();
+    for link in configs {
+        let name: Callsign = link.name.parse()?;
+        let addr = match resolve(&link.address, ipv4).await {
+            Ok(addr) => Some(addr),
+            Err(e) => {
+                warn!("Failed to resolve interlink {} ({}): {}", name, link.address, e);
+                None
+            }
+        };
+        for mapping in &link.modules {
+            links.push(Interlink::new(name.clone(), link.address.clone(), addr, *mapping));
+        }
+    }
+    Ok(links)
+}
@@ -1,13 +1,27 @@
+pub mod acl;
+pub mod ban;
 pub mod callsign;
+pub mod cidr;
 pub mod crc;
+pub mod dedup;
+pub mod lsf;
+pub mod meta;
 pub mod packet;
 pub mod config;
 pub mod reflector;
 pub mod module;
 pub mod peer;
+pub mod interlink;
+pub mod parrot;
+pub mod quality;
+pub mod ratelimit;
+pub mod recorder;
+pub mod reload;
 pub mod router;
 pub mod control;
 pub mod server;
+pub mod shutdown;
 pub mod state;
 pub mod api;
+pub mod admin;
 pub mod ws;
\ No newline at end of file
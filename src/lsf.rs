@@ -0,0 +1,80 @@
+use serde::Serialize;
+
+/// Payload carried by a stream, from bits 1..2 of the LSF TYPE field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DataType {
+    Reserved,
+    Data,
+    Voice,
+    VoiceData,
+}
+
+/// Encryption applied to the payload, from bits 3..4 of the LSF TYPE field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EncryptionType {
+    None,
+    Scrambler,
+    Aes,
+    Other,
+}
+
+/// Decoded LSF TYPE field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct LsfType {
+    pub stream: bool,
+    pub data_type: DataType,
+    pub encryption: EncryptionType,
+    pub encryption_subtype: u8,
+    pub can: u8,
+    pub reserved: u8,
+}
+
+impl LsfType {
+    pub fn from_u16(raw: u16) -> Self {
+        let data_type = match (raw >> 1) & 0x3 {
+            0b01 => DataType::Data,
+            0b10 => DataType::Voice,
+            0b11 => DataType::VoiceData,
+            _ => DataType::Reserved,
+        };
+        let encryption = match (raw >> 3) & 0x3 {
+            0b01 => EncryptionType::Scrambler,
+            0b10 => EncryptionType::Aes,
+            0b11 => EncryptionType::Other,
+            _ => EncryptionType::None,
+        };
+
+        Self {
+            stream: raw & 0x1 != 0,
+            data_type,
+            encryption,
+            encryption_subtype: ((raw >> 5) & 0x3) as u8,
+            can: ((raw >> 7) & 0xF) as u8,
+            reserved: ((raw >> 11) & 0x1F) as u8,
+        }
+    }
+
+    pub fn to_u16(&self) -> u16 {
+        let data_type = match self.data_type {
+            DataType::Reserved => 0b00,
+            DataType::Data => 0b01,
+            DataType::Voice => 0b10,
+            DataType::VoiceData => 0b11,
+        };
+        let encryption = match self.encryption {
+            EncryptionType::None => 0b00,
+            EncryptionType::Scrambler => 0b01,
+            EncryptionType::Aes => 0b10,
+            EncryptionType::Other => 0b11,
+        };
+
+        (self.stream as u16)
+            | (data_type << 1)
+            | (encryption << 3)
+            | (((self.encryption_subtype & 0x3) as u16) << 5)
+            | (((self.can & 0xF) as u16) << 7)
+            | (((self.reserved & 0x1F) as u16) << 11)
+    }
+}
@@ -2,10 +2,15 @@ use m17_reflector::config::Config;
 use clap::Parser;
 use log::info;
 use std::sync::Arc;
-use tokio::sync::{Mutex, broadcast};
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::sync::{Mutex, broadcast, mpsc, watch};
 
 use m17_reflector::reflector::Reflector;
+use m17_reflector::admin::{self, AdminState};
 use m17_reflector::api;
+use m17_reflector::reload::{self, ReloadRequest, Reloader};
+use m17_reflector::shutdown;
 use m17_reflector::ws;
 
 #[derive(Parser)]
@@ -26,26 +31,87 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let (tx, _rx) = broadcast::channel::<String>(100);
 
-    tokio::spawn(run_api_server(state.clone(), tx.clone()));
+    let socket = m17_reflector::server::bind(&config).await?;
 
-    m17_reflector::server::run_with_state(&config, state, tx).await?;
+    let admin_token = config
+        .admin_token
+        .as_ref()
+        .map(|t| t.expose().to_string())
+        .filter(|t| !t.is_empty());
+    let (config_tx, config_rx) = watch::channel(Arc::new(config));
+    let (reload_tx, reload_rx) = mpsc::channel(4);
+    tokio::spawn(reload::run_reload_task(
+        Reloader {
+            path: cli.config.clone(),
+            reflector: state.clone(),
+            socket: Arc::clone(&socket),
+            config: config_tx,
+        },
+        reload_rx,
+    ));
 
-    Ok(())
+    let (stop_tx, stop_rx) = watch::channel(false);
+    let api = tokio::spawn(run_api_server(
+        state.clone(),
+        Arc::clone(&socket),
+        tx.clone(),
+        admin_token,
+        reload_tx,
+        stop_rx,
+    ));
+
+    let current_config = config_rx.clone();
+    // Peers are disconnected whether the server stops on a signal or on
+    // an error; the error is returned once shutdown is complete.
+    let result = tokio::select! {
+        result = m17_reflector::server::run_with_state(config_rx, state.clone(), Arc::clone(&socket), tx) => result,
+        _ = shutdown::wait_for_signal() => Ok(()),
+    };
+
+    shutdown::disconnect_all(&state, &socket).await;
+    let _ = stop_tx.send(true);
+    let grace = Duration::from_secs(current_config.borrow().shutdown_grace);
+    if tokio::time::timeout(grace, api).await.is_err() {
+        info!("API server did not stop within the grace period");
+    }
+    info!("Reflector stopped");
+
+    result
 }
 
 async fn run_api_server(
     state: Arc<Mutex<Reflector>>,
+    socket: Arc<UdpSocket>,
     tx: broadcast::Sender<String>,
+    admin_token: Option<String>,
+    reload: mpsc::Sender<ReloadRequest>,
+    mut stop: watch::Receiver<bool>,
 ) {
     use axum::routing::get;
     use std::net::SocketAddr;
 
-    let app = api::create_router(state.clone())
+    let mut app = api::create_router(state.clone());
+    if let Some(token) = admin_token {
+        app = app.merge(admin::create_router(AdminState {
+            reflector: state.clone(),
+            socket,
+            tx: tx.clone(),
+            token: token.into(),
+            reload,
+        }));
+        info!("Admin API enabled");
+    }
+    let app = app
         .route("/ws", get(move |ws| ws::ws_handler(ws, axum::extract::State(state.clone()), tx.subscribe())));
 
     let addr = SocketAddr::from(([0, 0, 0, 0], 8080));
     info!("API server running on http://{}", addr);
 
     let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    axum::serve(listener, app)
+        .with_graceful_shutdown(async move {
+            let _ = stop.wait_for(|stop| *stop).await;
+        })
+        .await
+        .unwrap();
 }
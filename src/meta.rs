@@ -0,0 +1,161 @@
+use crate::lsf::{EncryptionType, LsfType};
+use serde::Serialize;
+
+/// Encryption subtype used for text data META when encryption is off.
+pub const META_SUBTYPE_TEXT: u8 = 0b00;
+/// Encryption subtype used for GNSS position META when encryption is off.
+pub const META_SUBTYPE_GNSS: u8 = 0b01;
+
+/// One block of a META text message.
+///
+/// The control byte holds a bitmap of the blocks that make up the whole
+/// message in its upper nibble and the bit of this block in its lower
+/// nibble; the remaining 13 bytes are text.
+#[derive(Debug, Clone, Copy)]
+pub struct TextBlock {
+    pub total_mask: u8,
+    pub block_mask: u8,
+    pub text: [u8; 13],
+}
+
+impl TextBlock {
+    pub fn decode(lsf_type: &LsfType, meta: &[u8; 14]) -> Option<Self> {
+        if lsf_type.encryption != EncryptionType::None
+            || lsf_type.encryption_subtype != META_SUBTYPE_TEXT
+        {
+            return None;
+        }
+
+        let total_mask = meta[0] >> 4;
+        let block_mask = meta[0] & 0x0F;
+        if block_mask.count_ones() != 1 || total_mask & block_mask == 0 {
+            return None;
+        }
+
+        Some(Self {
+            total_mask,
+            block_mask,
+            text: meta[1..14].try_into().unwrap(),
+        })
+    }
+
+    fn index(&self) -> usize {
+        self.block_mask.trailing_zeros() as usize
+    }
+}
+
+/// Collects META text blocks of a stream until the message is complete.
+#[derive(Debug, Default)]
+pub struct TextAssembler {
+    total_mask: u8,
+    received_mask: u8,
+    blocks: [[u8; 13]; 4],
+}
+
+impl TextAssembler {
+    /// Adds a block and returns the full message once every block
+    /// announced by the control byte has been seen.
+    pub fn push(&mut self, block: TextBlock) -> Option<String> {
+        if block.total_mask != self.total_mask {
+            *self = Self {
+                total_mask: block.total_mask,
+                ..Self::default()
+            };
+        }
+
+        self.blocks[block.index()] = block.text;
+        self.received_mask |= block.block_mask;
+
+        if self.received_mask != self.total_mask {
+            return None;
+        }
+
+        let count = (u8::BITS - self.total_mask.leading_zeros()) as usize;
+        let bytes: Vec<u8> = self.blocks[..count].iter().flatten().copied().collect();
+        let text = String::from_utf8_lossy(&bytes)
+            .trim_end_matches(['\0', ' '])
+            .to_string();
+        Some(text)
+    }
+}
+
+/// Kind of station reporting a GNSS position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StationType {
+    Fixed,
+    Mobile,
+    Handheld,
+    Unknown,
+}
+
+/// Position decoded from GNSS META.
+///
+/// Altitude is in metres and speed in km/h; both are only present when
+/// the sender flags them as valid.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct GnssPosition {
+    pub data_source: u8,
+    pub station_type: StationType,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub altitude: Option<f32>,
+    pub speed: Option<f32>,
+    pub bearing: Option<u16>,
+}
+
+impl GnssPosition {
+    pub fn decode(lsf_type: &LsfType, meta: &[u8; 14]) -> Option<Self> {
+        if lsf_type.encryption != EncryptionType::None
+            || lsf_type.encryption_subtype != META_SUBTYPE_GNSS
+        {
+            return None;
+        }
+
+        let station_type = match meta[1] {
+            0 => StationType::Fixed,
+            1 => StationType::Mobile,
+            2 => StationType::Handheld,
+            _ => StationType::Unknown,
+        };
+
+        let flags = meta[8];
+        let mut latitude =
+            meta[2] as f64 + u16::from_be_bytes([meta[3], meta[4]]) as f64 / 65536.0;
+        let mut longitude =
+            meta[5] as f64 + u16::from_be_bytes([meta[6], meta[7]]) as f64 / 65536.0;
+        if flags & 0x01 != 0 {
+            latitude = -latitude;
+        }
+        if flags & 0x02 != 0 {
+            longitude = -longitude;
+        }
+        if latitude.abs() > 90.0 || longitude.abs() > 180.0 {
+            return None;
+        }
+
+        let altitude = (flags & 0x04 != 0).then(|| {
+            let feet = u16::from_be_bytes([meta[9], meta[10]]) as f32 - 1500.0;
+            feet * 0.3048
+        });
+
+        let (speed, bearing) = if flags & 0x08 != 0 {
+            (
+                Some(meta[13] as f32 * 1.609_344),
+                Some(u16::from_be_bytes([meta[11], meta[12]]) % 360),
+            )
+        } else {
+            (None, None)
+        };
+
+        Some(Self {
+            data_source: meta[0],
+            station_type,
+            latitude,
+            longitude,
+            altitude,
+            speed,
+            bearing,
+        })
+    }
+}
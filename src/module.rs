@@ -1,9 +1,16 @@
+use crate::config::{AccessMode, ModuleConfig};
 use crate::peer::Peer;
 use std::collections::HashMap;
 use std::net::SocketAddr;
+use std::time::Duration;
 
 pub struct Module {
     pub name: char,
+    pub description: String,
+    pub access: AccessMode,
+    pub talk_timeout: Option<Duration>,
+    pub max_clients: Option<usize>,
+    pub linked: bool,
     pub peers: HashMap<SocketAddr, Peer>,
     pub stats: ModuleStats,
 }
@@ -11,17 +18,38 @@ pub struct Module {
 pub struct ModuleStats {
     pub total_streams: u64,
     pub total_frames: u64,
+    pub total_packets: u64,
+    /// CONN/LSTN refused because a client limit was reached.
+    pub refused_connections: u64,
 }
 
 impl Module {
-    pub fn new(name: char) -> Self {
-        Self {
-            name,
+    pub fn new(config: &ModuleConfig) -> Self {
+        let mut module = Self {
+            name: config.name,
+            description: String::new(),
+            access: AccessMode::Open,
+            talk_timeout: None,
+            max_clients: None,
+            linked: true,
             peers: HashMap::new(),
             stats: ModuleStats {
                 total_streams: 0,
                 total_frames: 0,
+                total_packets: 0,
+                refused_connections: 0,
             },
-        }
+        };
+        module.apply_config(config);
+        module
+    }
+
+    /// Takes over the settings of `config`, keeping peers and counters.
+    pub fn apply_config(&mut self, config: &ModuleConfig) {
+        self.description = config.description.clone();
+        self.access = config.access;
+        self.talk_timeout = config.talk_timeout.map(Duration::from_secs);
+        self.max_clients = config.max_clients;
+        self.linked = config.linked;
     }
 }
@@ -1,36 +1,157 @@
-use crate::callsign::decode_callsign;
+use crate::callsign::Callsign;
 use crate::crc::crc16_m17;
+use crate::lsf::LsfType;
 use std::convert::TryInto;
+use std::time::Duration;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Packet {
     Control(ControlKind),
     Stream(StreamPacket),
-    // PacketMode(PacketModePacket), // future
+    PacketMode(PacketModePacket),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum ControlKind {
-    Conn { from: String, module: char },
-    Lstn { from: String, module: char },
+    Conn { from: Callsign, module: char },
+    Lstn { from: Callsign, module: char },
     Ackn,
     Nack,
-    Ping { from: String },
-    Pong { from: String },
-    Disc { from: String },
+    Ping { from: Callsign },
+    Pong { from: Callsign },
+    Disc { from: Callsign },
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct StreamPacket {
     pub stream_id: u16,
-    pub dst: String,
-    pub src: String,
+    pub dst: Callsign,
+    pub src: Callsign,
+    pub lsf_type: LsfType,
+    pub meta: [u8; 14],
     pub frame_num: u16,
     pub last_frame: bool,
     pub payload: [u8; 16],
     pub crc_ok: bool,
 }
 
+#[derive(Debug, Clone)]
+pub struct PacketModePacket {
+    pub dst: Callsign,
+    pub src: Callsign,
+    pub lsf_type: LsfType,
+    pub meta: [u8; 14],
+    pub lsf_crc_ok: bool,
+    pub protocol: u8,
+    pub payload: Vec<u8>,
+    pub crc_ok: bool,
+}
+
+/// Duration of one stream frame.
+pub const FRAME_DURATION: Duration = Duration::from_millis(40);
+
+/// Packet mode protocol identifiers carried in the first payload byte.
+pub const PACKET_PROTOCOL_RAW: u8 = 0x00;
+pub const PACKET_PROTOCOL_AX25: u8 = 0x01;
+pub const PACKET_PROTOCOL_APRS: u8 = 0x02;
+pub const PACKET_PROTOCOL_6LOWPAN: u8 = 0x03;
+pub const PACKET_PROTOCOL_IPV4: u8 = 0x04;
+pub const PACKET_PROTOCOL_SMS: u8 = 0x05;
+pub const PACKET_PROTOCOL_WINLINK: u8 = 0x06;
+
+/// Maximum size of the packet mode payload including its trailing CRC.
+pub const PACKET_MAX_PAYLOAD: usize = 825;
+
+impl Packet {
+    /// Serializes the packet into its wire format, the inverse of
+    /// [`parse_packet`].
+    pub fn encode(&self) -> Vec<u8> {
+        match self {
+            Packet::Control(ctrl) => ctrl.encode(),
+            Packet::Stream(stream) => stream.encode(),
+            Packet::PacketMode(packet) => packet.encode(),
+        }
+    }
+}
+
+impl ControlKind {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(11);
+        match self {
+            ControlKind::Conn { from, module } => {
+                out.extend_from_slice(b"CONN");
+                out.extend_from_slice(&from.to_bytes());
+                out.push(*module as u8);
+            }
+            ControlKind::Lstn { from, module } => {
+                out.extend_from_slice(b"LSTN");
+                out.extend_from_slice(&from.to_bytes());
+                out.push(*module as u8);
+            }
+            ControlKind::Ackn => out.extend_from_slice(b"ACKN"),
+            ControlKind::Nack => out.extend_from_slice(b"NACK"),
+            ControlKind::Ping { from } => {
+                out.extend_from_slice(b"PING");
+                out.extend_from_slice(&from.to_bytes());
+            }
+            ControlKind::Pong { from } => {
+                out.extend_from_slice(b"PONG");
+                out.extend_from_slice(&from.to_bytes());
+            }
+            ControlKind::Disc { from } => {
+                out.extend_from_slice(b"DISC");
+                out.extend_from_slice(&from.to_bytes());
+            }
+        }
+        out
+    }
+}
+
+impl StreamPacket {
+    /// Serializes the frame with a freshly computed CRC. `crc_ok` is
+    /// ignored, so the result always parses with a valid CRC.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(54);
+        out.extend_from_slice(b"M17 ");
+        out.extend_from_slice(&self.stream_id.to_be_bytes());
+        out.extend_from_slice(&self.dst.to_bytes());
+        out.extend_from_slice(&self.src.to_bytes());
+        out.extend_from_slice(&self.lsf_type.to_u16().to_be_bytes());
+        out.extend_from_slice(&self.meta);
+
+        let mut frame_num = self.frame_num & 0x7FFF;
+        if self.last_frame {
+            frame_num |= 0x8000;
+        }
+        out.extend_from_slice(&frame_num.to_be_bytes());
+        out.extend_from_slice(&self.payload);
+
+        let crc = crc16_m17(&out);
+        out.extend_from_slice(&crc.to_be_bytes());
+        out
+    }
+}
+
+impl PacketModePacket {
+    /// Serializes the packet with freshly computed LSF and packet CRCs.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(37 + self.payload.len());
+        out.extend_from_slice(b"M17P");
+        out.extend_from_slice(&self.dst.to_bytes());
+        out.extend_from_slice(&self.src.to_bytes());
+        out.extend_from_slice(&self.lsf_type.to_u16().to_be_bytes());
+        out.extend_from_slice(&self.meta);
+        let lsf_crc = crc16_m17(&out[4..32]);
+        out.extend_from_slice(&lsf_crc.to_be_bytes());
+
+        out.push(self.protocol);
+        out.extend_from_slice(&self.payload);
+        let crc = crc16_m17(&out[34..]);
+        out.extend_from_slice(&crc.to_be_bytes());
+        out
+    }
+}
+
 #[derive(Debug)]
 pub enum PacketError {
     InvalidLength,
@@ -52,7 +173,7 @@ pub fn parse_packet(data: &[u8]) -> Result<Packet, PacketError> {
         b"PONG" => parse_pong(data),
         b"DISC" => parse_disc(data),
         b"M17 " => parse_stream(data),
-        b"M17P" => Err(PacketError::InvalidMagic), // TODO: implement packet mode
+        b"M17P" => parse_packet_mode(data),
         _ => Err(PacketError::InvalidMagic),
     }
 }
@@ -61,7 +182,7 @@ fn parse_conn(data: &[u8]) -> Result<Packet, PacketError> {
     if data.len() < 11 {
         return Err(PacketError::InvalidLength);
     }
-    let from = decode_callsign(data[4..10].try_into().unwrap());
+    let from = Callsign::from_bytes(data[4..10].try_into().unwrap());
     let module = data[10] as char;
     Ok(Packet::Control(ControlKind::Conn { from, module }))
 }
@@ -70,7 +191,7 @@ fn parse_lstn(data: &[u8]) -> Result<Packet, PacketError> {
     if data.len() < 11 {
         return Err(PacketError::InvalidLength);
     }
-    let from = decode_callsign(data[4..10].try_into().unwrap());
+    let from = Callsign::from_bytes(data[4..10].try_into().unwrap());
     let module = data[10] as char;
     Ok(Packet::Control(ControlKind::Lstn { from, module }))
 }
@@ -79,7 +200,7 @@ fn parse_ping(data: &[u8]) -> Result<Packet, PacketError> {
     if data.len() < 10 {
         return Err(PacketError::InvalidLength);
     }
-    let from = decode_callsign(data[4..10].try_into().unwrap());
+    let from = Callsign::from_bytes(data[4..10].try_into().unwrap());
     Ok(Packet::Control(ControlKind::Ping { from }))
 }
 
@@ -87,7 +208,7 @@ fn parse_pong(data: &[u8]) -> Result<Packet, PacketError> {
     if data.len() < 10 {
         return Err(PacketError::InvalidLength);
     }
-    let from = decode_callsign(data[4..10].try_into().unwrap());
+    let from = Callsign::from_bytes(data[4..10].try_into().unwrap());
     Ok(Packet::Control(ControlKind::Pong { from }))
 }
 
@@ -95,7 +216,7 @@ fn parse_disc(data: &[u8]) -> Result<Packet, PacketError> {
     if data.len() < 10 {
         return Err(PacketError::InvalidLength);
     }
-    let from = decode_callsign(data[4..10].try_into().unwrap());
+    let from = Callsign::from_bytes(data[4..10].try_into().unwrap());
     Ok(Packet::Control(ControlKind::Disc { from }))
 }
 
@@ -114,8 +235,10 @@ fn parse_stream(data: &[u8]) -> Result<Packet, PacketError> {
 
     let stream_id = u16::from_be_bytes(data[4..6].try_into().unwrap());
 
-    let dst = decode_callsign(data[6..12].try_into().unwrap());
-    let src = decode_callsign(data[12..18].try_into().unwrap());
+    let dst = Callsign::from_bytes(data[6..12].try_into().unwrap());
+    let src = Callsign::from_bytes(data[12..18].try_into().unwrap());
+    let lsf_type = LsfType::from_u16(u16::from_be_bytes(data[18..20].try_into().unwrap()));
+    let meta: [u8; 14] = data[20..34].try_into().unwrap();
 
     let frame_num_raw = u16::from_be_bytes(data[34..36].try_into().unwrap());
     let last_frame = (frame_num_raw & 0x8000) != 0;
@@ -127,9 +250,154 @@ fn parse_stream(data: &[u8]) -> Result<Packet, PacketError> {
         stream_id,
         dst,
         src,
+        lsf_type,
+        meta,
         frame_num,
         last_frame,
         payload,
         crc_ok,
     }))
 }
+
+fn parse_packet_mode(data: &[u8]) -> Result<Packet, PacketError> {
+    // magic (4) + LSF (30) + protocol byte (1) + CRC (2)
+    if data.len() < 37 || data.len() > 34 + PACKET_MAX_PAYLOAD {
+        return Err(PacketError::InvalidLength);
+    }
+
+    let lsf_crc_calc = crc16_m17(&data[4..32]);
+    let lsf_crc_field = u16::from_be_bytes(data[32..34].try_into().unwrap());
+    let lsf_crc_ok = lsf_crc_calc == lsf_crc_field;
+
+    let end = data.len() - 2;
+    let crc_calc = crc16_m17(&data[34..end]);
+    let crc_field = u16::from_be_bytes(data[end..].try_into().unwrap());
+    let crc_ok = crc_calc == crc_field;
+
+    if !lsf_crc_ok || !crc_ok {
+        log::debug!("CRC check failed for packet mode frame (continuing to route)");
+    }
+
+    let dst = Callsign::from_bytes(data[4..10].try_into().unwrap());
+    let src = Callsign::from_bytes(data[10..16].try_into().unwrap());
+    let lsf_type = LsfType::from_u16(u16::from_be_bytes(data[16..18].try_into().unwrap()));
+    let meta: [u8; 14] = data[18..32].try_into().unwrap();
+
+    let protocol = data[34];
+    let payload = data[35..end].to_vec();
+
+    Ok(Packet::PacketMode(PacketModePacket {
+        dst,
+        src,
+        lsf_type,
+        meta,
+        lsf_crc_ok,
+        protocol,
+        payload,
+        crc_ok,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn callsign(text: &str) -> Callsign {
+        text.parse().unwrap()
+    }
+
+    fn reparse(bytes: &[u8]) -> Packet {
+        parse_packet(bytes).expect("encoded packet parses")
+    }
+
+    #[test]
+    fn control_packets_round_trip() {
+        let from = callsign("N0CALL-7");
+        let packets = [
+            ControlKind::Conn { from: from.clone(), module: 'A' },
+            ControlKind::Lstn { from: from.clone(), module: 'Z' },
+            ControlKind::Ackn,
+            ControlKind::Nack,
+            ControlKind::Ping { from: from.clone() },
+            ControlKind::Pong { from: callsign("M17-ABC") },
+            ControlKind::Disc { from: callsign("#ALL") },
+        ];
+        for pkt in packets {
+            let bytes = pkt.encode();
+            let Packet::Control(parsed) = reparse(&bytes) else {
+                panic!("{:?} did not parse as control", pkt);
+            };
+            assert_eq!(parsed.encode(), bytes);
+            match (&pkt, &parsed) {
+                (ControlKind::Conn { from: a, module: m }, ControlKind::Conn { from: b, module: n })
+                | (ControlKind::Lstn { from: a, module: m }, ControlKind::Lstn { from: b, module: n }) => {
+                    assert_eq!(a, b);
+                    assert_eq!(m, n);
+                }
+                (ControlKind::Ping { from: a }, ControlKind::Ping { from: b })
+                | (ControlKind::Pong { from: a }, ControlKind::Pong { from: b })
+                | (ControlKind::Disc { from: a }, ControlKind::Disc { from: b }) => assert_eq!(a, b),
+                (ControlKind::Ackn, ControlKind::Ackn) | (ControlKind::Nack, ControlKind::Nack) => {}
+                _ => panic!("{:?} parsed as {:?}", pkt, parsed),
+            }
+        }
+    }
+
+    #[test]
+    fn stream_packet_round_trips() {
+        for (last_frame, raw_type) in [(false, 0x0005), (true, 0xF8A5)] {
+            let stream = StreamPacket {
+                stream_id: 0xBEEF,
+                dst: Callsign::Broadcast,
+                src: callsign("N0CALL"),
+                lsf_type: LsfType::from_u16(raw_type),
+                meta: *b"HELLO, WORLD!!",
+                frame_num: 0x1234,
+                last_frame,
+                payload: [0x5A; 16],
+                crc_ok: true,
+            };
+            let bytes = stream.encode();
+            let Packet::Stream(parsed) = reparse(&bytes) else {
+                panic!("stream did not parse");
+            };
+            assert!(parsed.crc_ok);
+            assert_eq!(parsed.stream_id, stream.stream_id);
+            assert_eq!(parsed.dst, stream.dst);
+            assert_eq!(parsed.src, stream.src);
+            assert_eq!(parsed.lsf_type, stream.lsf_type);
+            assert_eq!(parsed.lsf_type.to_u16(), raw_type);
+            assert_eq!(parsed.meta, stream.meta);
+            assert_eq!(parsed.frame_num, stream.frame_num);
+            assert_eq!(parsed.last_frame, last_frame);
+            assert_eq!(parsed.payload, stream.payload);
+            assert_eq!(parsed.encode(), bytes);
+        }
+    }
+
+    #[test]
+    fn packet_mode_round_trips() {
+        let packet = PacketModePacket {
+            dst: callsign("#ALL"),
+            src: callsign("N0CALL/P"),
+            lsf_type: LsfType::from_u16(0x0003),
+            meta: [0; 14],
+            lsf_crc_ok: true,
+            protocol: PACKET_PROTOCOL_SMS,
+            payload: b"Hello\0".to_vec(),
+            crc_ok: true,
+        };
+        let bytes = packet.encode();
+        let Packet::PacketMode(parsed) = reparse(&bytes) else {
+            panic!("packet mode frame did not parse");
+        };
+        assert!(parsed.lsf_crc_ok);
+        assert!(parsed.crc_ok);
+        assert_eq!(parsed.dst, packet.dst);
+        assert_eq!(parsed.src, packet.src);
+        assert_eq!(parsed.lsf_type, packet.lsf_type);
+        assert_eq!(parsed.protocol, packet.protocol);
+        assert_eq!(parsed.payload, packet.payload);
+        assert_eq!(parsed.encode(), bytes);
+    }
+}
@@ -0,0 +1,141 @@
+use crate::callsign::Callsign;
+use crate::lsf::LsfType;
+use crate::packet::{FRAME_DURATION, StreamPacket};
+use crate::reflector::StreamKey;
+use log::info;
+use std::collections::HashMap;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::net::UdpSocket;
+
+/// A transmission captured by the parrot, waiting to be played back.
+pub struct Recording {
+    pub peer: SocketAddr,
+    pub src: Callsign,
+    pub reply_from: Callsign,
+    pub lsf_type: LsfType,
+    pub meta: [u8; 14],
+    pub payloads: Vec<[u8; 16]>,
+    pub last_frame: Instant,
+}
+
+#[derive(Default)]
+pub struct Parrot {
+    recordings: HashMap<StreamKey, Recording>,
+}
+
+impl Parrot {
+    /// Buffers a frame of a parrot stream, keeping at most `max_frames`
+    /// payloads. Returns the recording once its last frame arrives.
+    pub fn record(
+        &mut self,
+        key: StreamKey,
+        stream: &StreamPacket,
+        reply_from: &Callsign,
+        max_frames: usize,
+    ) -> Option<Recording> {
+        let recording = self
+            .recordings
+            .entry(key)
+            .or_insert_with(|| Recording {
+                peer: key.peer,
+                src: stream.src.clone(),
+                reply_from: reply_from.clone(),
+                lsf_type: stream.lsf_type,
+                meta: stream.meta,
+                payloads: Vec::new(),
+                last_frame: Instant::now(),
+            });
+
+        if recording.payloads.len() < max_frames {
+            recording.payloads.push(stream.payload);
+        }
+        recording.last_frame = Instant::now();
+
+        if stream.last_frame {
+            self.recordings.remove(&key)
+        } else {
+            None
+        }
+    }
+
+    /// Removes recordings whose sender went quiet without a last frame.
+    pub fn take_stale(&mut self, timeout: Duration) -> Vec<Recording> {
+        let stale: Vec<StreamKey> = self
+            .recordings
+            .iter()
+            .filter(|(_, r)| r.last_frame.elapsed() > timeout)
+            .map(|(key, _)| *key)
+            .collect();
+
+        stale
+            .into_iter()
+            .filter_map(|key| self.recordings.remove(&key))
+            .collect()
+    }
+}
+
+impl Recording {
+    /// Builds the playback frames addressed back to the original sender.
+    pub fn frames(&self, stream_id: u16) -> Vec<Vec<u8>> {
+        let count = self.payloads.len();
+        self.payloads
+            .iter()
+            .enumerate()
+            .map(|(i, payload)| {
+                StreamPacket {
+                    stream_id,
+                    dst: self.src.clone(),
+                    src: self.reply_from.clone(),
+                    lsf_type: self.lsf_type,
+                    meta: self.meta,
+                    frame_num: i as u16,
+                    last_frame: i + 1 == count,
+                    payload: *payload,
+                    crc_ok: true,
+                }
+                .encode()
+            })
+            .collect()
+    }
+}
+
+/// Picks a stream ID for a stream synthesized for `peer` by hashing the
+/// current wall-clock time and the peer address with a randomly keyed
+/// hasher, so IDs differ between replays and between peers.
+pub fn new_stream_id(peer: SocketAddr) -> u16 {
+    let mut hasher = RandomState::new().build_hasher();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    hasher.write_u128(now.as_nanos());
+    peer.hash(&mut hasher);
+    hasher.finish() as u16
+}
+
+/// Plays a recording back to its sender in real time.
+pub async fn replay(recording: Recording, socket: Arc<UdpSocket>) {
+    if recording.payloads.is_empty() {
+        return;
+    }
+
+    let stream_id = new_stream_id(recording.peer);
+    info!(
+        "Parrot replay [{}]: {} frames to {} ({})",
+        stream_id,
+        recording.payloads.len(),
+        recording.src,
+        recording.peer
+    );
+
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let mut interval = tokio::time::interval(FRAME_DURATION);
+    for frame in recording.frames(stream_id) {
+        interval.tick().await;
+        let _ = socket.send_to(&frame, recording.peer).await;
+    }
+}
@@ -1,7 +1,9 @@
+use crate::callsign::Callsign;
+use crate::reflector::StreamKey;
 use std::net::SocketAddr;
 
 pub struct Peer {
-    pub callsign: String,
+    pub callsign: Callsign,
     pub address: SocketAddr,
     pub connected_at: std::time::SystemTime,
     pub last_seen: std::time::Instant,
@@ -11,11 +13,11 @@ pub struct Peer {
     pub bytes_out: u64,
     pub is_link: bool,
     pub listen_only: bool,
-    pub receiving_unicast: Option<u16>,
+    pub receiving_unicast: Option<StreamKey>,
 }
 
 impl Peer {
-    pub fn new(callsign: String, address: SocketAddr) -> Self {
+    pub fn new(callsign: Callsign, address: SocketAddr) -> Self {
         Self {
             callsign,
             address,
@@ -31,13 +33,13 @@ impl Peer {
         }
     }
 
-    pub fn new_link(callsign: String, address: SocketAddr) -> Self {
+    pub fn new_link(callsign: Callsign, address: SocketAddr) -> Self {
         let mut peer = Self::new(callsign, address);
         peer.is_link = true;
         peer
     }
 
-    pub fn new_listen(callsign: String, address: SocketAddr) -> Self {
+    pub fn new_listen(callsign: Callsign, address: SocketAddr) -> Self {
         let mut peer = Self::new(callsign, address);
         peer.listen_only = true;
         peer
@@ -0,0 +1,77 @@
+use crate::packet::FRAME_DURATION;
+use serde::Serialize;
+use std::time::Instant;
+
+/// Frame numbers are 15 bits wide and wrap around.
+const FRAME_NUM_MASK: u16 = 0x7FFF;
+/// Differences below this are treated as frames ahead of the highest seen.
+const FORWARD_WINDOW: u16 = 0x4000;
+/// Number of frames behind the highest seen that are tracked for reordering.
+const HISTORY: u16 = 64;
+
+/// Link quality counters of a single stream.
+///
+/// Jitter is the smoothed deviation of frame arrival times from the 40 ms
+/// frame interval, in milliseconds, as in RFC 3550.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StreamQuality {
+    pub lost: u32,
+    pub duplicates: u32,
+    pub out_of_order: u32,
+    pub bad_crc: u32,
+    pub jitter_ms: f64,
+    #[serde(skip)]
+    highest: Option<u16>,
+    #[serde(skip)]
+    seen: u64,
+    #[serde(skip)]
+    last_arrival: Option<Instant>,
+}
+
+impl StreamQuality {
+    pub fn record(&mut self, frame_num: u16, crc_ok: bool) {
+        if !crc_ok {
+            self.bad_crc += 1;
+        }
+
+        let frame_num = frame_num & FRAME_NUM_MASK;
+        let Some(highest) = self.highest else {
+            self.highest = Some(frame_num);
+            self.seen = 1;
+            self.last_arrival = Some(Instant::now());
+            return;
+        };
+
+        let ahead = frame_num.wrapping_sub(highest) & FRAME_NUM_MASK;
+        if ahead == 0 {
+            self.duplicates += 1;
+        } else if ahead < FORWARD_WINDOW {
+            self.lost += (ahead - 1) as u32;
+            self.seen = if ahead >= HISTORY { 0 } else { self.seen << ahead };
+            self.seen |= 1;
+            self.highest = Some(frame_num);
+            self.update_jitter(ahead);
+        } else {
+            let behind = highest.wrapping_sub(frame_num) & FRAME_NUM_MASK;
+            if behind < HISTORY && self.seen & (1 << behind) != 0 {
+                self.duplicates += 1;
+            } else {
+                if behind < HISTORY {
+                    self.seen |= 1 << behind;
+                }
+                self.out_of_order += 1;
+                self.lost = self.lost.saturating_sub(1);
+            }
+        }
+    }
+
+    fn update_jitter(&mut self, frames: u16) {
+        let now = Instant::now();
+        if let Some(last) = self.last_arrival.replace(now) {
+            let interval = now.duration_since(last).as_secs_f64() * 1000.0;
+            let expected = FRAME_DURATION.as_secs_f64() * 1000.0 * frames as f64;
+            let deviation = (interval - expected).abs();
+            self.jitter_ms += (deviation - self.jitter_ms) / 16.0;
+        }
+    }
+}
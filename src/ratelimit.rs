@@ -0,0 +1,231 @@
+use crate::config::RateLimitConfig;
+use log::warn;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Drops are counted against a source over this window when deciding
+/// whether to block it.
+const VIOLATION_WINDOW: Duration = Duration::from_secs(10);
+/// Sources idle for this long are forgotten.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PacketClass {
+    /// Control packets and anything that failed to parse.
+    Control,
+    /// Stream and packet mode frames.
+    Stream,
+}
+
+struct TokenBucket {
+    tokens: f64,
+    rate: f64,
+    burst: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: f64, burst: f64, now: Instant) -> Self {
+        Self {
+            tokens: burst,
+            rate,
+            burst,
+            updated: now,
+        }
+    }
+
+    fn set_limits(&mut self, rate: f64, burst: f64) {
+        self.rate = rate;
+        self.burst = burst;
+        self.tokens = self.tokens.min(burst);
+    }
+
+    fn try_take(&mut self, now: Instant) -> bool {
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+        self.updated = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+struct Source {
+    control: TokenBucket,
+    stream: TokenBucket,
+    violations: u32,
+    window_start: Instant,
+    blocked_until: Option<Instant>,
+    last_seen: Instant,
+}
+
+/// Counters shared with the API. They are updated from the receive loop
+/// without taking the reflector lock.
+#[derive(Debug, Default)]
+pub struct RateLimitStats {
+    dropped_control: AtomicU64,
+    dropped_stream: AtomicU64,
+    dropped_global: AtomicU64,
+    dropped_blocked: AtomicU64,
+    blocks: AtomicU64,
+    blocked_addresses: AtomicU64,
+    untracked: AtomicU64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RateLimitInfo {
+    pub dropped_control: u64,
+    pub dropped_stream: u64,
+    pub dropped_global: u64,
+    pub dropped_blocked: u64,
+    pub blocks: u64,
+    pub blocked_addresses: u64,
+    /// Packets only limited globally because `max_sources` was reached.
+    pub untracked: u64,
+}
+
+impl RateLimitStats {
+    pub fn info(&self) -> RateLimitInfo {
+        RateLimitInfo {
+            dropped_control: self.dropped_control.load(Ordering::Relaxed),
+            dropped_stream: self.dropped_stream.load(Ordering::Relaxed),
+            dropped_global: self.dropped_global.load(Ordering::Relaxed),
+            dropped_blocked: self.dropped_blocked.load(Ordering::Relaxed),
+            blocks: self.blocks.load(Ordering::Relaxed),
+            blocked_addresses: self.blocked_addresses.load(Ordering::Relaxed),
+            untracked: self.untracked.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Per-address token buckets for control and stream packets, a global
+/// packet budget, and temporary blocking of addresses that keep
+/// exceeding their limits.
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    global: TokenBucket,
+    sources: HashMap<IpAddr, Source>,
+    stats: Arc<RateLimitStats>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig, stats: Arc<RateLimitStats>) -> Self {
+        let global = TokenBucket::new(config.global_rate, config.global_burst, Instant::now());
+        Self {
+            config,
+            global,
+            sources: HashMap::new(),
+            stats,
+        }
+    }
+
+    /// Applies new limits, keeping current blocks.
+    pub fn reconfigure(&mut self, config: RateLimitConfig) {
+        self.global.set_limits(config.global_rate, config.global_burst);
+        for source in self.sources.values_mut() {
+            source.control.set_limits(config.control_rate, config.control_burst);
+            source.stream.set_limits(config.stream_rate, config.stream_burst);
+        }
+        self.config = config;
+    }
+
+    /// Whether a packet of `class` from `ip` may be processed.
+    pub fn check(&mut self, ip: IpAddr, class: PacketClass, now: Instant) -> bool {
+        if !self.config.enabled || self.config.exempt.iter().any(|net| net.contains(&ip)) {
+            return true;
+        }
+
+        // Unknown addresses are charged to the global budget before they
+        // are tracked, so a flood of spoofed sources cannot grow the table
+        // faster than the global rate. Once the table is full they are
+        // only limited globally.
+        let global_taken = !self.sources.contains_key(&ip);
+        if global_taken {
+            if !self.global.try_take(now) {
+                self.stats.dropped_global.fetch_add(1, Ordering::Relaxed);
+                return false;
+            }
+            if self.sources.len() >= self.config.max_sources {
+                self.stats.untracked.fetch_add(1, Ordering::Relaxed);
+                return true;
+            }
+        }
+
+        let config = &self.config;
+        let source = self.sources.entry(ip).or_insert_with(|| Source {
+            control: TokenBucket::new(config.control_rate, config.control_burst, now),
+            stream: TokenBucket::new(config.stream_rate, config.stream_burst, now),
+            violations: 0,
+            window_start: now,
+            blocked_until: None,
+            last_seen: now,
+        });
+        source.last_seen = now;
+
+        if let Some(until) = source.blocked_until {
+            if now < until {
+                self.stats.dropped_blocked.fetch_add(1, Ordering::Relaxed);
+                return false;
+            }
+            source.blocked_until = None;
+            self.stats.blocked_addresses.fetch_sub(1, Ordering::Relaxed);
+        }
+
+        let allowed = match class {
+            PacketClass::Control => source.control.try_take(now),
+            PacketClass::Stream => source.stream.try_take(now),
+        };
+        if !allowed {
+            let counter = match class {
+                PacketClass::Control => &self.stats.dropped_control,
+                PacketClass::Stream => &self.stats.dropped_stream,
+            };
+            counter.fetch_add(1, Ordering::Relaxed);
+
+            if now.duration_since(source.window_start) > VIOLATION_WINDOW {
+                source.window_start = now;
+                source.violations = 0;
+            }
+            source.violations += 1;
+            if self.config.block_threshold > 0 && source.violations >= self.config.block_threshold {
+                warn!(
+                    "Blocking {} for {} seconds after exceeding the packet rate limit",
+                    ip, self.config.block_seconds
+                );
+                source.blocked_until = Some(now + Duration::from_secs(self.config.block_seconds));
+                source.violations = 0;
+                self.stats.blocks.fetch_add(1, Ordering::Relaxed);
+                self.stats.blocked_addresses.fetch_add(1, Ordering::Relaxed);
+            }
+            return false;
+        }
+
+        if !global_taken && !self.global.try_take(now) {
+            self.stats.dropped_global.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+        true
+    }
+
+    /// Forgets idle sources and expired blocks.
+    pub fn prune(&mut self, now: Instant) {
+        let stats = &self.stats;
+        self.sources.retain(|_, source| {
+            if let Some(until) = source.blocked_until {
+                if now < until {
+                    return true;
+                }
+                source.blocked_until = None;
+                stats.blocked_addresses.fetch_sub(1, Ordering::Relaxed);
+            }
+            now.duration_since(source.last_seen) <= IDLE_TIMEOUT
+        });
+    }
+}
@@ -0,0 +1,219 @@
+use crate::config::RecordingConfig;
+use crate::packet::StreamPacket;
+use crate::reflector::StreamKey;
+use crate::state::RecordingInfo;
+use log::{error, info};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// File extension used for recordings.
+const EXTENSION: &str = "m17";
+
+/// Writes streams to disk as a sequence of records, each holding the
+/// receive time in milliseconds since the Unix epoch (u64), the frame
+/// length (u16) and the raw frame, all big-endian.
+///
+/// Files are created, written and flushed on a dedicated thread, so
+/// recording never blocks the caller on disk I/O. Dropping the recorder
+/// flushes all open recordings and waits for the thread to finish.
+pub struct Recorder {
+    config: RecordingConfig,
+    open: HashSet<StreamKey>,
+    commands: Option<mpsc::Sender<Command>>,
+    writer: Option<JoinHandle<()>>,
+}
+
+enum Command {
+    Open { key: StreamKey, name: String },
+    Write { key: StreamKey, record: Vec<u8> },
+    Finish(StreamKey),
+}
+
+impl Recorder {
+    pub fn new(config: RecordingConfig) -> io::Result<Self> {
+        fs::create_dir_all(&config.directory)?;
+        let (commands, receiver) = mpsc::channel();
+        let directory = config.directory.clone();
+        let writer = thread::Builder::new()
+            .name("recorder".into())
+            .spawn(move || run_writer(directory, receiver))?;
+        Ok(Self {
+            config,
+            open: HashSet::new(),
+            commands: Some(commands),
+            writer: Some(writer),
+        })
+    }
+
+    pub fn records_module(&self, module: char) -> bool {
+        self.config.modules.is_empty() || self.config.modules.contains(&module)
+    }
+
+    pub fn write_frame(&mut self, key: StreamKey, stream: &StreamPacket, data: &[u8]) {
+        if !self.open.contains(&key) {
+            if !self.records_module(key.module) {
+                return;
+            }
+            let name = file_name(stream, key.module);
+            info!("Recording stream {} to {}", stream.stream_id, name);
+            self.open.insert(key);
+            self.send(Command::Open { key, name });
+        }
+
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let mut record = Vec::with_capacity(10 + data.len());
+        record.extend_from_slice(&millis.to_be_bytes());
+        record.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        record.extend_from_slice(data);
+        self.send(Command::Write { key, record });
+    }
+
+    pub fn finish(&mut self, key: &StreamKey) {
+        if self.open.remove(key) {
+            self.send(Command::Finish(*key));
+        }
+    }
+
+    fn send(&self, command: Command) {
+        if let Some(commands) = &self.commands
+            && commands.send(command).is_err()
+        {
+            error!("Recording writer has stopped");
+        }
+    }
+
+    pub fn config(&self) -> &RecordingConfig {
+        &self.config
+    }
+
+    /// Deletes recordings older than `max_age`, then the oldest ones
+    /// until the directory is below `max_size` bytes.
+    pub fn rotate(config: &RecordingConfig) -> io::Result<()> {
+        let mut recordings = Self::list(&config.directory)?;
+        recordings.sort_by_key(|r| r.modified);
+
+        let max_age = Duration::from_secs(config.max_age);
+        let mut total: u64 = recordings.iter().map(|r| r.size).sum();
+        for recording in recordings {
+            let expired = recording.modified.elapsed().unwrap_or_default() > max_age;
+            if !expired && total <= config.max_size {
+                continue;
+            }
+            info!("Removing recording {}", recording.name);
+            fs::remove_file(config.directory.join(&recording.name))?;
+            total -= recording.size;
+        }
+        Ok(())
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.config.directory
+    }
+
+    /// Lists the recordings in `directory`.
+    pub fn list(directory: &Path) -> io::Result<Vec<RecordingInfo>> {
+        let mut recordings = Vec::new();
+        for entry in fs::read_dir(directory)? {
+            let entry = entry?;
+            let path = entry.path();
+            if path.extension().and_then(|e| e.to_str()) != Some(EXTENSION) {
+                continue;
+            }
+            let metadata = entry.metadata()?;
+            recordings.push(RecordingInfo {
+                name: entry.file_name().to_string_lossy().into_owned(),
+                size: metadata.len(),
+                modified: metadata.modified()?,
+            });
+        }
+        Ok(recordings)
+    }
+
+    /// Resolves a recording name from the API to its path, refusing
+    /// anything that is not a plain file name inside `directory`.
+    pub fn path(directory: &Path, name: &str) -> Option<PathBuf> {
+        let valid = !name.is_empty()
+            && name.ends_with(&format!(".{}", EXTENSION))
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+            && !name.contains("..");
+        if !valid {
+            return None;
+        }
+        let path = directory.join(name);
+        path.is_file().then_some(path)
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        // Closing the channel makes the writer flush every open file and
+        // exit.
+        self.commands = None;
+        if let Some(writer) = self.writer.take()
+            && writer.join().is_err()
+        {
+            error!("Recording writer panicked");
+        }
+    }
+}
+
+fn file_name(stream: &StreamPacket, module: char) -> String {
+    let started = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let source: String = stream
+        .src
+        .to_string()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+        .collect();
+    format!(
+        "{}_{}_{:04x}_{}.{}",
+        started, module, stream.stream_id, source, EXTENSION
+    )
+}
+
+fn run_writer(directory: PathBuf, commands: mpsc::Receiver<Command>) {
+    let mut files: HashMap<StreamKey, BufWriter<File>> = HashMap::new();
+    for command in commands {
+        match command {
+            Command::Open { key, name } => match File::create(directory.join(&name)) {
+                Ok(file) => {
+                    files.insert(key, BufWriter::new(file));
+                }
+                Err(e) => error!("Failed to create recording {}: {}", name, e),
+            },
+            Command::Write { key, record } => {
+                if let Some(file) = files.get_mut(&key)
+                    && let Err(e) = file.write_all(&record)
+                {
+                    error!("Failed to write recording for stream {}: {}", key.stream_id, e);
+                    files.remove(&key);
+                }
+            }
+            Command::Finish(key) => {
+                if let Some(mut file) = files.remove(&key)
+                    && let Err(e) = file.flush()
+                {
+                    error!("Failed to flush recording for stream {}: {}", key.stream_id, e);
+                }
+            }
+        }
+    }
+    for (key, mut file) in files {
+        if let Err(e) = file.flush() {
+            error!("Failed to flush recording for stream {}: {}", key.stream_id, e);
+        }
+    }
+}
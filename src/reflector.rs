@@ -1,79 +1,587 @@
-use crate::callsign::base_callsign;
+use crate::ban::{self, Ban, BanTarget};
+use crate::callsign::Callsign;
+use crate::config::{AccessMode, ClientLimits, Config, ModuleConfig};
+use crate::dedup::StreamDedup;
+use crate::interlink::{Interlink, LinkState};
+use crate::lsf::LsfType;
+use crate::meta::{GnssPosition, TextAssembler, TextBlock};
 use crate::module::Module;
+use crate::packet::StreamPacket;
+use crate::parrot::Parrot;
+use crate::quality::StreamQuality;
+use crate::ratelimit::RateLimitStats;
+use crate::recorder::Recorder;
 use crate::peer::Peer;
-use crate::state::{ClientInfo, ReflectorState, StreamInfo as ApiStreamInfo};
-use log::info;
+use crate::state::{
+    ClientInfo, InterlinkInfo, ModuleInfo, PositionInfo, ReflectorState,
+    StreamInfo as ApiStreamInfo,
+};
+use log::{error, info, warn};
+use thiserror::Error;
 use std::collections::{HashMap, HashSet, VecDeque};
-use std::net::SocketAddr;
-use std::time::{Instant, SystemTime};
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
 
 pub struct Reflector {
     pub name: String,
     pub modules: HashMap<char, Module>,
-    pub active_streams: HashMap<u16, StreamInfo>,
+    /// Modules each registered peer address is attached to: exactly one
+    /// for a client, one or more for a reflector link.
+    pub peer_modules: HashMap<SocketAddr, Vec<char>>,
+    pub active_streams: HashMap<StreamKey, StreamInfo>,
     pub recent_streams: VecDeque<StreamInfo>,
     pub start_time: Instant,
     pub user_map: HashMap<String, HashSet<SocketAddr>>,
+    pub positions: HashMap<String, Position>,
+    pub parrot: Parrot,
+    pub recorder: Option<Recorder>,
+    pub interlinks: Vec<Interlink>,
+    pub dedup: StreamDedup,
+    pub bans: Vec<Ban>,
+    next_ban_id: u64,
+    /// Peer addresses muted by an administrator, with the base callsign
+    /// they were connected as, so a mute survives reconnecting from the
+    /// same address or with the same callsign.
+    mutes: HashMap<SocketAddr, String>,
+    /// File the bans are saved to whenever they change.
+    ban_file: Option<PathBuf>,
+    pub rate_limit_stats: Arc<RateLimitStats>,
+    /// PING and DISC packets ignored because the sender is not connected.
+    pub ignored_control_packets: u64,
+}
+
+/// Identifies a stream by where it entered the reflector, so that equal
+/// stream IDs picked by unrelated senders never collide.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StreamKey {
+    pub module: char,
+    pub peer: SocketAddr,
+    pub stream_id: u16,
+}
+
+#[derive(Debug, Error)]
+pub enum StreamRejected {
+    #[error("module already has an active broadcast stream")]
+    ModuleBusy,
+    #[error("stream ID is already in use by {0}")]
+    Hijack(SocketAddr),
+    #[error("transmission exceeded the module talk timeout")]
+    TalkTimeout,
+}
+
+#[derive(Debug, Error)]
+pub enum ClientRejected {
+    #[error("reflector is full")]
+    ReflectorFull,
+    #[error("module is full")]
+    ModuleFull,
+    #[error("module has too many listeners")]
+    ListenersFull,
+}
+
+/// Last known position reported by a source callsign.
+pub struct Position {
+    pub position: GnssPosition,
+    pub module: char,
+    pub updated: Instant,
 }
 
 pub struct StreamInfo {
-    pub callsign: String,
-    pub destination: String,
+    pub callsign: Callsign,
+    pub destination: Callsign,
     pub module: char,
     pub start_time: Instant,
-    pub peer_callsign: String,
+    pub peer_callsign: Option<Callsign>,
     pub peer: SocketAddr,
     pub frames: u32,
+    pub lsf_type: LsfType,
+    pub meta: [u8; 14],
+    pub text: Option<String>,
+    pub text_assembler: TextAssembler,
+    pub quality: StreamQuality,
     pub is_broadcast: bool,
+    /// Set once the stream ran past the module talk timeout.
+    pub talk_timed_out: bool,
     pub last_frame: Instant,
     pub end_time: Option<Instant>,
 }
 
 impl Reflector {
-    pub fn new(name: &str, mod_names: &[char]) -> Self {
+    pub fn new(name: &str, module_configs: &[ModuleConfig]) -> Self {
         let mut modules = HashMap::new();
-        for name in mod_names {
-            modules.insert(*name, Module::new(*name));
+        for config in module_configs {
+            modules.insert(config.name, Module::new(config));
         }
         Self {
             name: name.to_string(),
             modules,
+            peer_modules: HashMap::new(),
             active_streams: HashMap::new(),
             recent_streams: VecDeque::with_capacity(50),
             start_time: Instant::now(),
             user_map: HashMap::new(),
+            positions: HashMap::new(),
+            parrot: Parrot::default(),
+            recorder: None,
+            interlinks: Vec::new(),
+            dedup: StreamDedup::default(),
+            bans: Vec::new(),
+            next_ban_id: 1,
+            mutes: HashMap::new(),
+            ban_file: None,
+            rate_limit_stats: Arc::new(RateLimitStats::default()),
+            ignored_control_packets: 0,
         }
     }
 
+    /// The module a client at `addr` is attached to, or the first module
+    /// of a reflector link.
+    pub fn peer_module(&self, addr: &SocketAddr) -> Option<char> {
+        self.peer_modules.get(addr)?.first().copied()
+    }
+
+    pub fn is_attached(&self, addr: &SocketAddr, module: char) -> bool {
+        self.peer_modules
+            .get(addr)
+            .is_some_and(|modules| modules.contains(&module))
+    }
+
+    /// Whether `addr` is a reflector link carrying more than one module,
+    /// whose frames name their module in the destination.
+    pub fn is_shared_link(&self, addr: &SocketAddr) -> bool {
+        self.peer_modules.get(addr).is_some_and(|modules| modules.len() > 1)
+    }
+
     pub fn find_peer_mut(&mut self, addr: &SocketAddr) -> Option<&mut Peer> {
-        for module in self.modules.values_mut() {
-            if let Some(peer) = module.peers.get_mut(addr) {
-                return Some(peer);
-            }
-        }
-        None
+        let module = self.peer_module(addr)?;
+        self.modules.get_mut(&module)?.peers.get_mut(addr)
     }
 
     pub fn find_peer(&self, addr: &SocketAddr) -> Option<&Peer> {
-        for module in self.modules.values() {
-            if let Some(peer) = module.peers.get(addr) {
-                return Some(peer);
+        let module = self.peer_module(addr)?;
+        self.modules.get(&module)?.peers.get(addr)
+    }
+
+    /// Marks the peer at `addr` as seen on every module it is attached to.
+    pub fn touch_peer(&mut self, addr: &SocketAddr) {
+        let Some(modules) = self.peer_modules.get(addr) else {
+            return;
+        };
+        let now = Instant::now();
+        for module in modules {
+            if let Some(peer) = self
+                .modules
+                .get_mut(module)
+                .and_then(|m| m.peers.get_mut(addr))
+            {
+                peer.last_seen = now;
             }
         }
-        None
     }
 
-    pub fn add_link_peer(&mut self, module: char, name: String, addr: SocketAddr) {
+    /// Registers `peer` on `module`. A reflector link is added to the
+    /// modules it already carries; a client is moved off the module it was
+    /// attached to before, keeping its counters. Returns the previous
+    /// module of a moved client, if any.
+    pub fn attach_peer(&mut self, module: char, mut peer: Peer) -> Option<char> {
+        if !self.modules.contains_key(&module) {
+            return None;
+        }
+        let addr = peer.address;
+        if peer.is_link
+            && self.find_peer(&addr).is_some_and(|p| p.is_link)
+            && let Some(modules) = self.peer_modules.get_mut(&addr)
+        {
+            if !modules.contains(&module) {
+                modules.push(module);
+            }
+            if let Some(m) = self.modules.get_mut(&module) {
+                m.peers.entry(addr).or_insert(peer);
+            }
+            return None;
+        }
+        let previous = self
+            .peer_modules
+            .insert(addr, vec![module])
+            .and_then(|modules| modules.first().copied());
+        if let Some(old) = previous
+            && let Some(old_peer) = self
+                .modules
+                .get_mut(&old)
+                .and_then(|m| m.peers.remove(&addr))
+        {
+            peer.connected_at = old_peer.connected_at;
+            peer.packets_in = old_peer.packets_in;
+            peer.packets_out = old_peer.packets_out;
+            peer.bytes_in = old_peer.bytes_in;
+            peer.bytes_out = old_peer.bytes_out;
+            if old != module {
+                info!(
+                    "{} ({}) moved from module {} to module {}",
+                    peer.callsign, addr, old, module
+                );
+            }
+        }
         if let Some(m) = self.modules.get_mut(&module) {
-            m.peers
-                .entry(addr)
-                .or_insert_with(|| Peer::new_link(name, addr));
+            m.peers.insert(addr, peer);
+        }
+        previous
+    }
+
+    /// Checks `limits` before a client at `addr` is attached to `module`,
+    /// counting refusals on the module. A client already on the module
+    /// is always admitted again.
+    pub fn admit_client(
+        &mut self,
+        module: char,
+        addr: &SocketAddr,
+        listen_only: bool,
+        limits: &ClientLimits,
+    ) -> Result<(), ClientRejected> {
+        let current = self.peer_module(addr);
+        if current == Some(module) {
+            return Ok(());
+        }
+        let clients = |m: &Module| m.peers.values().filter(|p| !p.is_link).count();
+
+        let total: usize = self.modules.values().map(clients).sum();
+
+        let result = if current.is_none() && limits.max_clients.is_some_and(|max| total >= max) {
+            Err(ClientRejected::ReflectorFull)
+        } else if let Some(m) = self.modules.get(&module)
+            && m
+                .max_clients
+                .or(limits.max_module_clients)
+                .is_some_and(|max| clients(m) >= max)
+        {
+            Err(ClientRejected::ModuleFull)
+        } else if let Some(m) = self.modules.get(&module)
+            && listen_only
+            && limits
+                .max_listen_clients
+                .is_some_and(|max| m.peers.values().filter(|p| p.listen_only).count() >= max)
+        {
+            Err(ClientRejected::ListenersFull)
+        } else {
+            Ok(())
+        };
+
+        if result.is_err()
+            && let Some(m) = self.modules.get_mut(&module)
+        {
+            m.stats.refused_connections += 1;
+        }
+        result
+    }
+
+    /// Attaches the outbound link to `addr` on `module`. Returns false if
+    /// a client is attached at the address.
+    pub fn add_link_peer(&mut self, module: char, name: Callsign, addr: SocketAddr) -> bool {
+        if let Some(peer) = self.find_peer(&addr)
+            && !peer.is_link
+        {
+            warn!(
+                "Cannot link module {} to {} ({}): address in use by client {}",
+                module, name, addr, peer.callsign
+            );
+            return false;
+        }
+        self.attach_peer(module, Peer::new_link(name, addr));
+        true
+    }
+
+    /// Detaches the peer at `addr` from `module` only, returning it. The
+    /// address stays registered while it is attached to other modules.
+    pub fn detach_peer(&mut self, module: char, addr: &SocketAddr) -> Option<Peer> {
+        let modules = self.peer_modules.get_mut(addr)?;
+        let index = modules.iter().position(|m| *m == module)?;
+        modules.remove(index);
+        let peer = self.modules.get_mut(&module).and_then(|m| m.peers.remove(addr));
+        if modules.is_empty() {
+            self.remove_peer(addr);
+        }
+        peer
+    }
+
+    /// Brings the modules in line with `configs`: new modules are added,
+    /// existing ones take over their new settings, and removed ones are
+    /// dropped after ending their streams. Returns the peers of removed
+    /// modules so they can be told about the disconnect.
+    pub fn apply_module_configs(&mut self, configs: &[ModuleConfig]) -> Vec<Peer> {
+        let removed: Vec<char> = self
+            .modules
+            .keys()
+            .filter(|name| !configs.iter().any(|c| c.name == **name))
+            .copied()
+            .collect();
+
+        let mut dropped = Vec::new();
+        for name in removed {
+            let ended: Vec<StreamKey> = self
+                .active_streams
+                .keys()
+                .filter(|k| k.module == name)
+                .copied()
+                .collect();
+            for key in &ended {
+                self.end_stream(key);
+            }
+            let Some(module) = self.modules.remove(&name) else {
+                continue;
+            };
+            for addr in module.peers.keys() {
+                if let Some(modules) = self.peer_modules.get_mut(addr) {
+                    modules.retain(|m| *m != name);
+                    if !modules.is_empty() {
+                        continue;
+                    }
+                }
+                self.peer_modules.remove(addr);
+                for addrs in self.user_map.values_mut() {
+                    addrs.remove(addr);
+                }
+            }
+            self.user_map.retain(|_, s| !s.is_empty());
+            info!("Removed module {} ({} peers)", name, module.peers.len());
+            // A link that still carries other modules is not disconnected.
+            dropped.extend(
+                module
+                    .peers
+                    .into_values()
+                    .filter(|peer| !self.peer_modules.contains_key(&peer.address)),
+            );
+        }
+
+        for config in configs {
+            match self.modules.get_mut(&config.name) {
+                Some(module) => module.apply_config(config),
+                None => {
+                    info!("Added module {}", config.name);
+                    self.modules.insert(config.name, Module::new(config));
+                }
+            }
+        }
+        dropped
+    }
+
+    /// Ends every active stream, drops the configured interlinks so they
+    /// are not reconnected, and removes all peers. Returns the peers so
+    /// they can be told about the disconnect.
+    pub fn disconnect_all(&mut self) -> Vec<Peer> {
+        let ended: Vec<StreamKey> = self.active_streams.keys().copied().collect();
+        for key in &ended {
+            self.end_stream(key);
+        }
+        self.interlinks.clear();
+        self.peer_modules.clear();
+        self.user_map.clear();
+        let mut seen = HashSet::new();
+        self.modules
+            .values_mut()
+            .flat_map(|module| module.peers.drain().map(|(_, peer)| peer))
+            .filter(|peer| seen.insert(peer.address))
+            .collect()
+    }
+
+    /// Re-checks connected peers against the access rules of `config`.
+    /// Clients that are no longer permitted and inbound links that are no
+    /// longer trusted or whose module no longer accepts links are removed
+    /// and returned; clients on modules that became listen-only are made
+    /// listeners. Outbound links are handled by `sync_interlinks`.
+    pub fn revoke_access(&mut self, config: &Config) -> Vec<Peer> {
+        let mut revoked = Vec::new();
+        for (name, module) in self.modules.iter_mut() {
+            for (addr, peer) in module.peers.iter_mut() {
+                let ip = addr.ip();
+                let permitted = if peer.is_link {
+                    let outbound = self
+                        .interlinks
+                        .iter()
+                        .any(|l| l.module == *name && l.address == Some(*addr));
+                    outbound
+                        || (module.linked
+                            && config
+                                .peer_reflectors
+                                .iter()
+                                .any(|p| p.matches(&peer.callsign, &ip)))
+                } else {
+                    config.client_permitted(*name, module.access, &peer.callsign, &ip)
+                };
+                if !permitted {
+                    revoked.push((*name, *addr));
+                } else if module.access == AccessMode::ListenOnly
+                    && !peer.is_link
+                    && !peer.listen_only
+                {
+                    peer.listen_only = true;
+                    info!("{} ({}) is now listening only on module {}", peer.callsign, addr, name);
+                }
+            }
+        }
+
+        let mut dropped = Vec::new();
+        for (module, addr) in revoked {
+            let Some(peer) = self.detach_peer(module, &addr) else {
+                continue;
+            };
+            info!("{} ({}) no longer permitted on module {}", peer.callsign, addr, module);
+            // A link that still carries other modules is not disconnected.
+            if !self.peer_modules.contains_key(&addr) {
+                dropped.push(peer);
+            }
+        }
+        dropped
+    }
+
+    /// Replaces the configured interlinks with `links`, keeping the state
+    /// of links that did not change. Links on missing or unlinked modules
+    /// are skipped. Returns the links that were removed.
+    pub fn sync_interlinks(&mut self, links: Vec<Interlink>) -> Vec<Interlink> {
+        let mut incoming = links;
+        let mut kept = Vec::new();
+        let mut removed = Vec::new();
+        for link in self.interlinks.drain(..) {
+            match incoming.iter().position(|l| l.same_link(&link)) {
+                Some(i) => {
+                    incoming.remove(i);
+                    kept.push(link);
+                }
+                None => removed.push(link),
+            }
+        }
+        kept.extend(incoming);
+
+        for link in kept {
+            if !self.modules.get(&link.module).is_some_and(|m| m.linked) {
+                warn!(
+                    "Skipping interlink {} on module {}: module missing or not linked",
+                    link.name, link.module
+                );
+                removed.push(link);
+            } else {
+                self.interlinks.push(link);
+            }
+        }
+        removed
+    }
+
+    /// Marks the link waiting on `addr` as established. Returns false if
+    /// no link was waiting for an answer from that address.
+    pub fn link_acknowledged(&mut self, addr: &SocketAddr) -> bool {
+        match self.pending_link(addr) {
+            Some(link) => {
+                link.on_ackn();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Marks the link waiting on `addr` as refused and detaches it from
+    /// its module.
+    pub fn link_refused(&mut self, addr: &SocketAddr) -> bool {
+        let Some(link) = self.pending_link(addr) else {
+            return false;
+        };
+        link.on_nack(Instant::now());
+        let module = link.module;
+        self.detach_peer(module, addr);
+        true
+    }
+
+    /// The link to `addr` whose CONN has waited longest for an answer.
+    /// Replies carry no module, so a remote reflector linked on several
+    /// modules is assumed to answer its CONNs in order.
+    fn pending_link(&mut self, addr: &SocketAddr) -> Option<&mut Interlink> {
+        self.interlinks
+            .iter_mut()
+            .filter(|link| link.address == Some(*addr) && link.state == LinkState::Connecting)
+            .min_by_key(|link| link.conn_sent)
+    }
+
+    /// Points every link to `host` at `new`, moving the link peers from
+    /// the previous address and forcing a fresh CONN.
+    pub fn update_link_address(&mut self, host: &str, new: SocketAddr) {
+        let now = Instant::now();
+        let mut moved = Vec::new();
+        for link in self.interlinks.iter_mut() {
+            if link.host != host || link.address == Some(new) {
+                continue;
+            }
+            info!("Interlink {} ({}) now resolves to {}", link.name, host, new);
+            if let Some(old) = link.address.replace(new) {
+                moved.push((link.module, old));
+            }
+            link.on_lost(now);
         }
+
+        for (module, old) in moved {
+            for addrs in self.user_map.values_mut() {
+                if addrs.remove(&old) {
+                    addrs.insert(new);
+                }
+            }
+            if let Some(mut peer) = self.detach_peer(module, &old) {
+                peer.address = new;
+                self.attach_peer(module, peer);
+            }
+        }
+    }
+
+    /// The module a frame from the shared link at `addr` belongs to. Its
+    /// destination names the module on the reflector that accepted the
+    /// link: one of ours as `<our name> <module>`, or, on an outbound
+    /// link, the remote module as `<link name> <remote module>`.
+    pub fn link_frame_module(&self, addr: &SocketAddr, dst: &Callsign) -> Option<char> {
+        let Callsign::Normal(text) = dst else {
+            return None;
+        };
+        let (name, letter) = text.rsplit_once(' ')?;
+        let mut letters = letter.chars();
+        let (Some(letter), None) = (letters.next(), letters.next()) else {
+            return None;
+        };
+        let module = if name == self.name {
+            letter
+        } else {
+            self.interlinks
+                .iter()
+                .find(|link| {
+                    link.address == Some(*addr)
+                        && link.remote_module == letter
+                        && link.name.to_string() == name
+                })?
+                .module
+        };
+        self.is_attached(addr, module).then_some(module)
     }
 
+    /// The destination that names `module` in frames sent to `addr`, or
+    /// `None` unless `addr` is a shared link. See `link_frame_module`.
+    pub fn link_destination(&self, addr: &SocketAddr, module: char) -> Option<Callsign> {
+        if !self.is_shared_link(addr) {
+            return None;
+        }
+        let outbound = self
+            .interlinks
+            .iter()
+            .find(|link| link.address == Some(*addr) && link.module == module);
+        let text = match outbound {
+            Some(link) => format!("{} {}", link.name, link.remote_module),
+            None => format!("{} {}", self.name, module),
+        };
+        text.parse().ok()
+    }
+
+    /// Removes the peer at `addr` from every module it is attached to.
     pub fn remove_peer(&mut self, addr: &SocketAddr) {
-        for module in self.modules.values_mut() {
-            module.peers.remove(addr);
+        for module in self.peer_modules.remove(addr).unwrap_or_default() {
+            if let Some(m) = self.modules.get_mut(&module) {
+                m.peers.remove(addr);
+            }
         }
         for addrs in self.user_map.values_mut() {
             addrs.remove(addr);
@@ -81,43 +589,158 @@ impl Reflector {
         self.user_map.retain(|_, s| !s.is_empty());
     }
 
-    pub fn record_user(&mut self, callsign: &str, addr: SocketAddr) {
-        let base = base_callsign(callsign);
+    /// Loads the bans saved in `path` and keeps it up to date from now on.
+    pub fn load_bans(&mut self, path: PathBuf) -> io::Result<()> {
+        self.bans = ban::load(&path)?;
+        self.next_ban_id = self.bans.iter().map(|b| b.id + 1).max().unwrap_or(1);
+        info!("Loaded {} bans from {}", self.bans.len(), path.display());
+        self.ban_file = Some(path);
+        Ok(())
+    }
+
+    fn save_bans(&self) {
+        if let Some(path) = &self.ban_file
+            && let Err(e) = ban::save(path, &self.bans)
+        {
+            error!("Failed to save bans to {}: {}", path.display(), e);
+        }
+    }
+
+    /// Adds a ban and returns it together with the addresses of the
+    /// connected peers it covers.
+    pub fn add_ban(
+        &mut self,
+        target: BanTarget,
+        reason: Option<String>,
+        expires: Option<SystemTime>,
+    ) -> (Ban, Vec<SocketAddr>) {
+        let ban = Ban {
+            id: self.next_ban_id,
+            target,
+            reason,
+            created: SystemTime::now(),
+            expires,
+        };
+        self.next_ban_id += 1;
+        info!("Added ban {}: {:?}", ban.id, ban.target);
+        let covered = self
+            .modules
+            .values()
+            .flat_map(|m| m.peers.values())
+            .filter(|p| ban.matches(&p.callsign, &p.address.ip()))
+            .map(|p| p.address)
+            .collect();
+        self.bans.push(ban.clone());
+        self.save_bans();
+        (ban, covered)
+    }
+
+    pub fn remove_ban(&mut self, id: u64) -> bool {
+        let before = self.bans.len();
+        self.bans.retain(|b| b.id != id);
+        let removed = self.bans.len() != before;
+        if removed {
+            self.save_bans();
+        }
+        removed
+    }
+
+    /// Mutes the peer at `addr`, returning its callsign, or `None` if no
+    /// peer is connected there.
+    pub fn mute(&mut self, addr: SocketAddr) -> Option<Callsign> {
+        let callsign = self.find_peer(&addr)?.callsign.clone();
+        self.mutes.insert(addr, callsign.base());
+        Some(callsign)
+    }
+
+    /// Lifts the mute on `addr` and, if a peer is connected there, on its
+    /// base callsign. Returns the unmuted callsign, or `None` if neither
+    /// was muted.
+    pub fn unmute(&mut self, addr: &SocketAddr) -> Option<String> {
+        let base = self.find_peer(addr).map(|peer| peer.callsign.base());
+        let removed = self.mutes.remove(addr);
+        let before = self.mutes.len();
+        if let Some(base) = &base {
+            self.mutes.retain(|_, muted| muted != base);
+        }
+        if self.mutes.len() != before {
+            return base;
+        }
+        removed
+    }
+
+    pub fn is_muted(&self, callsign: &Callsign, addr: &SocketAddr) -> bool {
+        if self.mutes.contains_key(addr) {
+            return true;
+        }
+        let base = callsign.base();
+        self.mutes.values().any(|muted| *muted == base)
+    }
+
+    pub fn is_banned(&self, callsign: &Callsign, ip: &IpAddr) -> bool {
+        let now = SystemTime::now();
+        self.bans
+            .iter()
+            .any(|b| !b.expired(now) && b.matches(callsign, ip))
+    }
+
+    pub fn prune_bans(&mut self) {
+        let now = SystemTime::now();
+        let before = self.bans.len();
+        self.bans.retain(|b| {
+            let expired = b.expired(now);
+            if expired {
+                info!("Ban {} expired", b.id);
+            }
+            !expired
+        });
+        if self.bans.len() != before {
+            self.save_bans();
+        }
+    }
+
+    pub fn record_user(&mut self, callsign: &Callsign, addr: SocketAddr) {
         self.user_map
-            .entry(base)
-            .or_insert_with(HashSet::new)
+            .entry(callsign.base())
+            .or_default()
             .insert(addr);
     }
 
-    pub fn find_user_peers(&self, callsign: &str) -> Vec<SocketAddr> {
-        let base = base_callsign(callsign);
+    pub fn find_user_peers(&self, callsign: &Callsign) -> Vec<SocketAddr> {
         self.user_map
-            .get(&base)
+            .get(&callsign.base())
             .map(|s| s.iter().cloned().collect())
             .unwrap_or_default()
     }
 
+    /// Records a frame of the stream identified by `key`, starting the
+    /// stream on its first frame.
     pub fn record_stream_frame(
         &mut self,
-        stream_id: u16,
-        callsign: &str,
-        module: char,
-        destination: &str,
-        peer: SocketAddr,
+        key: StreamKey,
+        stream: &StreamPacket,
         is_broadcast: bool,
-    ) -> bool {
+    ) -> Result<(), StreamRejected> {
+        let module = key.module;
         if let Some(mod_ref) = self.modules.get_mut(&module) {
             mod_ref.stats.total_frames += 1;
         }
-        if !self.active_streams.contains_key(&stream_id) {
-            if is_broadcast {
-                if self
+        if !self.active_streams.contains_key(&key) {
+            if let Some(owner) = self
+                .active_streams
+                .keys()
+                .find(|k| k.module == module && k.stream_id == key.stream_id)
+            {
+                return Err(StreamRejected::Hijack(owner.peer));
+            }
+
+            if is_broadcast
+                && self
                     .active_streams
                     .values()
                     .any(|s| s.module == module && s.is_broadcast)
-                {
-                    return false;
-                }
+            {
+                return Err(StreamRejected::ModuleBusy);
             }
 
             if let Some(mod_ref) = self.modules.get_mut(&module) {
@@ -125,56 +748,115 @@ impl Reflector {
             }
             info!(
                 "Stream start [{}]: {} -> {} on module {}",
-                stream_id, callsign, destination, module
+                key.stream_id, stream.src, stream.dst, module
             );
             self.active_streams.insert(
-                stream_id,
+                key,
                 StreamInfo {
-                    callsign: callsign.to_string(),
-                    destination: destination.to_string(),
+                    callsign: stream.src.clone(),
+                    destination: stream.dst.clone(),
                     module,
                     start_time: Instant::now(),
                     last_frame: Instant::now(),
-                    peer_callsign: self
-                        .find_peer(&peer)
-                        .map(|p| p.callsign.clone())
-                        .unwrap_or_default(),
-                    peer,
-                    frames: 1,
+                    peer_callsign: self.find_peer(&key.peer).map(|p| p.callsign.clone()),
+                    peer: key.peer,
+                    frames: 0,
+                    lsf_type: stream.lsf_type,
+                    meta: stream.meta,
+                    text: None,
+                    text_assembler: TextAssembler::default(),
+                    quality: StreamQuality::default(),
                     is_broadcast,
+                    talk_timed_out: false,
                     end_time: None,
                 },
             );
-            true
-        } else {
-            if let Some(entry) = self.active_streams.get_mut(&stream_id) {
-                entry.frames += 1;
-                entry.last_frame = Instant::now();
+        }
+
+        let talk_timeout = self.modules.get(&module).and_then(|m| m.talk_timeout);
+        let Some(entry) = self.active_streams.get_mut(&key) else {
+            return Ok(());
+        };
+        entry.last_frame = Instant::now();
+        if talk_timeout.is_some_and(|t| entry.start_time.elapsed() > t) {
+            if !entry.talk_timed_out {
+                entry.talk_timed_out = true;
+                warn!(
+                    "Stream [{}] from {} cut off on module {}: talk timeout reached",
+                    key.stream_id, entry.callsign, module
+                );
+            }
+            return Err(StreamRejected::TalkTimeout);
+        }
+        entry.frames += 1;
+        entry.meta = stream.meta;
+        entry.quality.record(stream.frame_num, stream.crc_ok);
+        self.record_meta(key, stream);
+        Ok(())
+    }
+
+    /// Counts a frame dropped for its CRC against its stream, if active.
+    pub fn record_bad_crc(&mut self, peer: SocketAddr, stream_id: u16) {
+        if let Some(entry) = self
+            .active_streams
+            .iter_mut()
+            .find(|(k, _)| k.peer == peer && k.stream_id == stream_id)
+            .map(|(_, s)| s)
+        {
+            entry.quality.bad_crc += 1;
+        }
+    }
+
+    fn record_meta(&mut self, key: StreamKey, stream: &StreamPacket) {
+        let Some(entry) = self.active_streams.get_mut(&key) else {
+            return;
+        };
+        if let Some(position) = GnssPosition::decode(&stream.lsf_type, &stream.meta) {
+            self.positions.insert(
+                entry.callsign.to_string(),
+                Position {
+                    position,
+                    module: entry.module,
+                    updated: Instant::now(),
+                },
+            );
+            return;
+        }
+        let Some(block) = TextBlock::decode(&stream.lsf_type, &stream.meta) else {
+            return;
+        };
+        if let Some(text) = entry.text_assembler.push(block) {
+            if entry.text.as_deref() != Some(text.as_str()) {
+                info!("Stream text [{}]: {}: \"{}\"", key.stream_id, entry.callsign, text);
             }
-            true
+            entry.text = Some(text);
         }
     }
 
-    pub fn end_stream(&mut self, stream_id: u16) {
-        if let Some(info) = self.active_streams.remove(&stream_id) {
+    pub fn prune_positions(&mut self, max_age: Duration) {
+        self.positions.retain(|_, p| p.updated.elapsed() <= max_age);
+    }
+
+    pub fn end_stream(&mut self, key: &StreamKey) {
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.finish(key);
+        }
+        for module in self.modules.values_mut() {
+            for peer in module.peers.values_mut() {
+                if peer.receiving_unicast.as_ref() == Some(key) {
+                    peer.receiving_unicast = None;
+                }
+            }
+        }
+        if let Some(mut info) = self.active_streams.remove(key) {
             let duration = info.start_time.elapsed().as_secs_f32();
             info!(
                 "Stream end [{}]: {} -> {} on module {} — {} frames, {:.2} sec",
-                stream_id, info.callsign, info.destination, info.module, info.frames, duration
+                key.stream_id, info.callsign, info.destination, info.module, info.frames, duration
             );
 
-            self.recent_streams.push_back(StreamInfo {
-                callsign: info.callsign,
-                destination: info.destination,
-                module: info.module,
-                peer_callsign: info.peer_callsign,
-                peer: info.peer,
-                start_time: info.start_time,
-                frames: info.frames,
-                last_frame: info.last_frame,
-                is_broadcast: info.is_broadcast,
-                end_time: Some(Instant::now()),
-            });
+            info.end_time = Some(Instant::now());
+            self.recent_streams.push_back(info);
 
             if self.recent_streams.len() > 50 {
                 self.recent_streams.pop_front();
@@ -204,12 +886,14 @@ impl Reflector {
                 clients.insert(
                     peer.address.to_string(),
                     ClientInfo {
-                        callsign: peer.callsign.clone(),
+                        callsign: peer.callsign.to_string(),
                         module: *module_name,
                         connected_since: peer.connected_at,
                         last_seen,
                         packets_in: peer.packets_in,
                         bytes_in: peer.bytes_in,
+                        is_link: peer.is_link,
+                        muted: self.is_muted(&peer.callsign, &peer.address),
                     },
                 );
             }
@@ -218,17 +902,22 @@ impl Reflector {
         let active_streams: Vec<ApiStreamInfo> = self
             .active_streams
             .iter()
-            .map(|(id, s)| ApiStreamInfo {
-                source: s.callsign.clone(),
-                peer: if s.peer_callsign.is_empty() {
-                    s.peer.to_string()
-                } else {
-                    s.peer_callsign.clone()
-                },
-                destination: s.destination.clone(),
+            .map(|(key, s)| ApiStreamInfo {
+                source: s.callsign.to_string(),
+                peer: s
+                    .peer_callsign
+                    .as_ref()
+                    .map(|c| c.to_string())
+                    .unwrap_or_else(|| s.peer.to_string()),
+                destination: s.destination.to_string(),
                 module: s.module,
-                stream_id: *id,
+                stream_id: key.stream_id,
                 frames: s.frames,
+                data_type: s.lsf_type.data_type,
+                encryption: s.lsf_type.encryption,
+                can: s.lsf_type.can,
+                text: s.text.clone(),
+                quality: s.quality.clone(),
                 started_at: SystemTime::now() - s.start_time.elapsed(),
                 ended_at: None,
             })
@@ -238,27 +927,79 @@ impl Reflector {
             .recent_streams
             .iter()
             .map(|s| ApiStreamInfo {
-                peer: if s.peer_callsign.is_empty() {
-                    s.peer.to_string()
-                } else {
-                    s.peer_callsign.clone()
-                },
-                source: s.callsign.clone(),
-                destination: s.destination.clone(),
+                peer: s
+                    .peer_callsign
+                    .as_ref()
+                    .map(|c| c.to_string())
+                    .unwrap_or_else(|| s.peer.to_string()),
+                source: s.callsign.to_string(),
+                destination: s.destination.to_string(),
                 module: s.module,
                 stream_id: 0,
                 frames: s.frames,
+                data_type: s.lsf_type.data_type,
+                encryption: s.lsf_type.encryption,
+                can: s.lsf_type.can,
+                text: s.text.clone(),
+                quality: s.quality.clone(),
                 started_at: SystemTime::now() - s.start_time.elapsed(),
                 ended_at: s.end_time.map(|t| SystemTime::now() - t.elapsed()),
             })
             .collect();
 
+        let positions: Vec<PositionInfo> = self
+            .positions
+            .iter()
+            .map(|(callsign, p)| PositionInfo {
+                callsign: callsign.clone(),
+                module: p.module,
+                position: p.position,
+                updated_at: SystemTime::now() - p.updated.elapsed(),
+            })
+            .collect();
+
+        let interlinks: Vec<InterlinkInfo> = self
+            .interlinks
+            .iter()
+            .map(|l| InterlinkInfo {
+                name: l.name.to_string(),
+                host: l.host.clone(),
+                address: l.address.map(|a| a.to_string()),
+                module: l.module,
+                remote_module: l.remote_module,
+                state: l.state,
+                attempts: l.attempts,
+                state_since: l.state_since,
+            })
+            .collect();
+
         ReflectorState {
             start_time: self.start_time,
             name: self.name.clone(),
             clients,
             active_streams,
             recent_streams,
+            positions,
+            interlinks,
+            rate_limit: self.rate_limit_stats.info(),
+            ignored_control_packets: self.ignored_control_packets,
+            modules: self
+                .modules
+                .iter()
+                .map(|(name, m)| {
+                    (
+                        *name,
+                        ModuleInfo {
+                            description: m.description.clone(),
+                            access: m.access,
+                            talk_timeout: m.talk_timeout.map(|t| t.as_secs()),
+                            max_clients: m.max_clients,
+                            linked: m.linked,
+                            refused_connections: m.stats.refused_connections,
+                        },
+                    )
+                })
+                .collect(),
         }
     }
 }
@@ -0,0 +1,123 @@
+use crate::config::Config;
+use crate::control::send_disc;
+use crate::interlink;
+use crate::reflector::Reflector;
+use log::{error, info, warn};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::{mpsc, oneshot, watch, Mutex};
+
+/// A reload asked for through the admin API, answered with the outcome.
+pub type ReloadRequest = oneshot::Sender<Result<(), String>>;
+
+/// Re-reads the configuration file and applies it to the running
+/// reflector.
+pub struct Reloader {
+    pub path: String,
+    pub reflector: Arc<Mutex<Reflector>>,
+    pub socket: Arc<UdpSocket>,
+    pub config: watch::Sender<Arc<Config>>,
+}
+
+impl Reloader {
+    /// Loads and validates the file, then adds, updates and removes
+    /// modules and interlinks and re-checks connected peers against the
+    /// new access rules. Peers of removed modules, removed links and
+    /// peers that are no longer permitted receive DISC; everyone else
+    /// stays connected. Nothing is changed if the file is invalid.
+    pub async fn reload(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let mut new = Config::load_from_file(&self.path)?;
+        let old = self.config.borrow().clone();
+        // Settings that only take effect at startup keep their running
+        // values, so the published configuration matches what is in use.
+        if new.bind_address != old.bind_address {
+            warn!("bind_address changed; restart to apply");
+            new.bind_address = old.bind_address.clone();
+        }
+        if new.reflector_name != old.reflector_name {
+            warn!("reflector_name changed; restart to apply");
+            new.reflector_name = old.reflector_name.clone();
+        }
+        if new.recording != old.recording {
+            warn!("recording changed; restart to apply");
+            new.recording = old.recording.clone();
+        }
+        if new.admin_token != old.admin_token {
+            warn!("admin_token changed; restart to apply");
+            new.admin_token = old.admin_token.clone();
+        }
+        if new.ban_file != old.ban_file {
+            warn!("ban_file changed; restart to apply");
+            new.ban_file = old.ban_file.clone();
+        }
+        if new.strict_crc != old.strict_crc {
+            info!(
+                "CRC enforcement mode: {}",
+                if new.strict_crc { "STRICT" } else { "PERMISSIVE" }
+            );
+        }
+
+        let ipv4 = self.socket.local_addr()?.is_ipv4();
+        let links = interlink::from_config(&new.interlinks, ipv4).await?;
+
+        let mut r = self.reflector.lock().await;
+        for peer in r.apply_module_configs(&new.modules) {
+            let _ = send_disc(&peer, &self.socket).await;
+        }
+        for link in r.sync_interlinks(links) {
+            let Some(addr) = link.address else {
+                continue;
+            };
+            let Some(peer) = r.detach_peer(link.module, &addr) else {
+                continue;
+            };
+            info!("Removing interlink {} on module {}", link.name, link.module);
+            // A link that still carries other modules stays connected.
+            if r.peer_module(&addr).is_none() {
+                let _ = send_disc(&peer, &self.socket).await;
+            }
+        }
+        for peer in r.revoke_access(&new) {
+            let _ = send_disc(&peer, &self.socket).await;
+        }
+        r.dedup.set_window(Duration::from_secs(new.loop_window));
+        drop(r);
+
+        self.config.send_replace(Arc::new(new));
+        info!("Configuration reloaded from {}", self.path);
+        Ok(())
+    }
+}
+
+/// Reloads the configuration on SIGHUP and on requests from the admin
+/// API.
+pub async fn run_reload_task(reloader: Reloader, mut requests: mpsc::Receiver<ReloadRequest>) {
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => Some(hangup),
+        Err(e) => {
+            error!("Failed to listen for SIGHUP: {}", e);
+            None
+        }
+    };
+
+    loop {
+        let reply = tokio::select! {
+            Some(_) = async { hangup.as_mut()?.recv().await } => {
+                info!("SIGHUP received, reloading configuration");
+                None
+            }
+            Some(reply) = requests.recv() => Some(reply),
+            else => return,
+        };
+
+        let result = reloader.reload().await.map_err(|e| e.to_string());
+        if let Err(e) = &result {
+            error!("Configuration reload rejected: {}", e);
+        }
+        if let Some(reply) = reply {
+            let _ = reply.send(result);
+        }
+    }
+}
@@ -1,6 +1,9 @@
-use crate::packet::StreamPacket;
-use crate::reflector::Reflector;
-use log::{debug, error, warn};
+use crate::callsign::Callsign;
+use crate::config::{AccessMode, Config};
+use crate::packet::{PacketModePacket, StreamPacket, PACKET_PROTOCOL_SMS};
+use crate::parrot;
+use crate::reflector::{Reflector, StreamKey, StreamRejected};
+use log::{debug, error, info, warn};
 use std::net::SocketAddr;
 use std::sync::Arc;
 use tokio::net::UdpSocket;
@@ -10,10 +13,9 @@ pub async fn route_stream_packet(
     stream: StreamPacket,
     data: &[u8],
     addr: SocketAddr,
-    reflector_name: &str,
+    config: &Config,
     reflector: &Arc<Mutex<Reflector>>,
     socket: &Arc<UdpSocket>,
-    strict_crc: bool,
     tx: broadcast::Sender<String>,
 ) {
     if !stream.crc_ok {
@@ -23,78 +25,363 @@ pub async fn route_stream_packet(
         );
     }
 
-    if strict_crc && !stream.crc_ok {
+    if config.strict_crc && !stream.crc_ok {
         error!(
             "Packet with bad CRC dropped: {} -> {} (stream {})",
             stream.src, stream.dst, stream.stream_id
         );
+        reflector.lock().await.record_bad_crc(addr, stream.stream_id);
         return;
     }
 
-    if stream.src == "INVALID" || stream.dst == "INVALID" {
-        warn!("Dropped invalid address stream packet");
+    if !addresses_valid(&stream.src, &stream.dst) {
         return;
     }
-    if stream.dst.starts_with("RESERVED-") || stream.src.starts_with("RESERVED-") {
-        warn!("Dropped reserved address stream packet");
+
+    let mut r = reflector.lock().await;
+
+    let Some(sender_module) = sender_module(&r, &stream.src, &stream.dst, addr) else {
+        return;
+    };
+
+    if !source_permitted(&r, config, sender_module, &stream.src, addr) {
+        debug!(
+            "Dropped traffic from denied source {} ({}) on module {}",
+            stream.src, addr, sender_module
+        );
         return;
     }
 
-    let mut r = reflector.lock().await;
+    let is_broadcast = r.is_shared_link(&addr)
+        || is_broadcast(&stream.dst, &config.reflector_name, sender_module);
 
-    let sender_module = r
-        .modules
-        .values()
-        .find(|m| m.peers.contains_key(&addr))
-        .map(|m| m.name);
+    if let Some(peer) = r.find_peer_mut(&addr) {
+        peer.increment_rx(data.len());
+    }
 
-    if sender_module.is_none() {
-        warn!(
-            "Stream from {} ({}) dropped: sender not registered",
-            stream.src, addr
+    if !r.dedup.check(&stream.src, stream.stream_id, addr) {
+        debug!(
+            "Dropped looped stream {} from {} arriving via {}",
+            stream.stream_id, stream.src, addr
         );
         return;
     }
-    let sender_module = sender_module.unwrap();
 
-    if let Some(peer) = r.find_peer(&addr) {
-        if peer.listen_only {
-            warn!(
-                "Dropped stream from listen-only peer {} ({})",
-                peer.callsign, addr
-            );
+    r.record_user(&stream.src, addr);
+
+    let key = StreamKey {
+        module: sender_module,
+        peer: addr,
+        stream_id: stream.stream_id,
+    };
+
+    if let Some(parrot) = &config.parrot
+        && parrot.handles(&stream.dst, sender_module)
+    {
+        if let Err(reason) = r.record_stream_frame(key, &stream, false) {
+            if !matches!(reason, StreamRejected::TalkTimeout) {
+                warn!("Parrot stream from {} ignored: {}", stream.src, reason);
+            }
             return;
         }
+        if let Some(recording) =
+            r.parrot
+                .record(key, &stream, &parrot.destination, parrot.max_frames())
+        {
+            r.end_stream(&key);
+            tokio::spawn(parrot::replay(recording, Arc::clone(socket)));
+        }
+        let _ = tx.send("update".into());
+        return;
     }
 
-    let reflector_call = format!("{} {}", reflector_name, sender_module);
-    let is_broadcast = 
-        stream.dst == "BROADCAST" || stream.dst == "ALL" || stream.dst == reflector_call;
+    if matches!(stream.dst, Callsign::Hash(_)) && !is_broadcast {
+        debug!(
+            "Dropped stream {} from {} to unsupported special address {}",
+            stream.stream_id, stream.src, stream.dst
+        );
+        return;
+    }
 
-    if let Some(peer) = r.find_peer_mut(&addr) {
-        peer.increment_rx(data.len());
+    let is_new = !r.active_streams.contains_key(&key);
+    if let Err(reason) = r.record_stream_frame(key, &stream, is_broadcast) {
+        if matches!(reason, StreamRejected::TalkTimeout) {
+            return;
+        }
+        warn!(
+            "Stream {} from {} ({}) ignored on module {}: {}",
+            stream.stream_id, stream.src, addr, sender_module, reason
+        );
+        return;
     }
+    let _ = tx.send("update".into());
 
-    r.record_user(&stream.src, addr);
+    if let Some(recorder) = r.recorder.as_mut() {
+        recorder.write_frame(key, &stream, data);
+    }
 
-    let is_new = !r.active_streams.contains_key(&stream.stream_id);
-    let allowed = r.record_stream_frame(
-        stream.stream_id,
-        &stream.src,
+    let targets = target_peers(
+        &r,
+        addr,
         sender_module,
+        &stream.src,
         &stream.dst,
-        addr,
         is_broadcast,
+        config.link_transit,
     );
-    if !allowed {
-        warn!(
-            "Stream from {} ignored: module {} already has an active stream",
-            stream.src, sender_module
+    for peer_addr in targets {
+        if is_broadcast
+            && let Some(p) = r.find_peer(&peer_addr)
+            && p.receiving_unicast.is_some()
+        {
+            continue;
+        }
+
+        let relabeled = if is_broadcast
+            && let Some(dst) = r.link_destination(&peer_addr, sender_module)
+        {
+            Some(StreamPacket { dst, ..stream.clone() }.encode())
+        } else {
+            None
+        };
+        let _ = socket.send_to(relabeled.as_deref().unwrap_or(data), peer_addr).await;
+
+        if let Some(p) = r.find_peer_mut(&peer_addr) {
+            p.increment_tx(data.len());
+            if !is_broadcast && is_new {
+                p.receiving_unicast = Some(key);
+            }
+        }
+    }
+
+    if stream.last_frame {
+        r.end_stream(&key);
+        let _ = tx.send("update".into());
+    }
+}
+
+pub async fn route_packet_mode(
+    packet: PacketModePacket,
+    data: &[u8],
+    addr: SocketAddr,
+    config: &Config,
+    reflector: &Arc<Mutex<Reflector>>,
+    socket: &Arc<UdpSocket>,
+    tx: broadcast::Sender<String>,
+) {
+    let crc_ok = packet.lsf_crc_ok && packet.crc_ok;
+    if !crc_ok {
+        debug!(
+            "Forwarding packet mode frame with bad CRC: {} -> {}",
+            packet.src, packet.dst
+        );
+    }
+
+    if config.strict_crc && !crc_ok {
+        error!(
+            "Packet mode frame with bad CRC dropped: {} -> {}",
+            packet.src, packet.dst
+        );
+        return;
+    }
+
+    if !addresses_valid(&packet.src, &packet.dst) {
+        return;
+    }
+
+    let mut r = reflector.lock().await;
+
+    let Some(sender_module) = sender_module(&r, &packet.src, &packet.dst, addr) else {
+        return;
+    };
+
+    if !source_permitted(&r, config, sender_module, &packet.src, addr) {
+        debug!(
+            "Dropped traffic from denied source {} ({}) on module {}",
+            packet.src, addr, sender_module
+        );
+        return;
+    }
+
+    let is_broadcast = r.is_shared_link(&addr)
+        || is_broadcast(&packet.dst, &config.reflector_name, sender_module);
+
+    if let Some(peer) = r.find_peer_mut(&addr) {
+        peer.increment_rx(data.len());
+    }
+
+    let crc = u16::from_be_bytes([data[data.len() - 2], data[data.len() - 1]]);
+    if !r.dedup.check_packet(&packet.src, crc, addr) {
+        debug!("Dropped looped packet from {} arriving via {}", packet.src, addr);
+        return;
+    }
+
+    r.record_user(&packet.src, addr);
+
+    if matches!(packet.dst, Callsign::Hash(_)) && !is_broadcast {
+        debug!(
+            "Dropped packet from {} to unsupported special address {}",
+            packet.src, packet.dst
         );
         return;
     }
+
+    if let Some(mod_ref) = r.modules.get_mut(&sender_module) {
+        mod_ref.stats.total_packets += 1;
+    }
+
+    if packet.protocol == PACKET_PROTOCOL_SMS {
+        info!(
+            "SMS ({} bytes): {} -> {} on module {}",
+            packet.payload.len(),
+            packet.src,
+            packet.dst,
+            sender_module
+        );
+    } else {
+        info!(
+            "Packet [protocol {:#04x}, {} bytes]: {} -> {} on module {}",
+            packet.protocol,
+            packet.payload.len(),
+            packet.src,
+            packet.dst,
+            sender_module
+        );
+    }
+
+    let targets = target_peers(
+        &r,
+        addr,
+        sender_module,
+        &packet.src,
+        &packet.dst,
+        is_broadcast,
+        config.link_transit,
+    );
+    for peer_addr in targets {
+        let relabeled = if is_broadcast
+            && let Some(dst) = r.link_destination(&peer_addr, sender_module)
+        {
+            Some(PacketModePacket { dst, ..packet.clone() }.encode())
+        } else {
+            None
+        };
+        let _ = socket.send_to(relabeled.as_deref().unwrap_or(data), peer_addr).await;
+
+        if let Some(p) = r.find_peer_mut(&peer_addr) {
+            p.increment_tx(data.len());
+        }
+    }
+
     let _ = tx.send("update".into());
+}
 
+fn addresses_valid(src: &Callsign, dst: &Callsign) -> bool {
+    match (src, dst) {
+        (Callsign::Invalid, _) | (_, Callsign::Invalid) => {
+            warn!("Dropped invalid address packet");
+            false
+        }
+        (Callsign::Reserved(_), _) | (_, Callsign::Reserved(_)) => {
+            warn!("Dropped reserved address packet");
+            false
+        }
+        (src, _) if !src.is_valid_source() => {
+            warn!("Dropped packet with special source address {}", src);
+            false
+        }
+        _ => true,
+    }
+}
+
+/// Returns the module the sender is registered on, or `None` if the
+/// sender is unknown, only allowed to listen or muted. Frames from a link
+/// carrying several modules must name their module in `dst`.
+fn sender_module(
+    r: &Reflector,
+    src: &Callsign,
+    dst: &Callsign,
+    addr: SocketAddr,
+) -> Option<char> {
+    let Some(peer) = r.find_peer(&addr) else {
+        warn!("Traffic from {} ({}) dropped: sender not registered", src, addr);
+        return None;
+    };
+
+    if peer.listen_only {
+        warn!(
+            "Dropped traffic from listen-only peer {} ({})",
+            peer.callsign, addr
+        );
+        return None;
+    }
+
+    if r.is_muted(&peer.callsign, &addr) {
+        debug!("Dropped traffic from muted peer {} ({})", peer.callsign, addr);
+        return None;
+    }
+
+    if r.is_shared_link(&addr) {
+        let module = r.link_frame_module(&addr, dst);
+        if module.is_none() {
+            debug!(
+                "Dropped traffic from {} via {} ({}): {} names no linked module",
+                src, peer.callsign, addr, dst
+            );
+        }
+        return module;
+    }
+
+    r.peer_module(&addr)
+}
+
+/// Whether `src` may transmit on `module` from `addr`. Clients are held to
+/// the same access lists as when connecting, so an admitted client cannot
+/// transmit as another callsign; links only to the deny lists, since
+/// their sources are users of the remote reflector.
+fn source_permitted(
+    r: &Reflector,
+    config: &Config,
+    module: char,
+    src: &Callsign,
+    addr: SocketAddr,
+) -> bool {
+    let ip = addr.ip();
+    if r.is_banned(src, &ip) {
+        return false;
+    }
+    if r.find_peer(&addr).is_some_and(|peer| peer.is_link) {
+        return !config.callsign_denied(module, src);
+    }
+    let access = r.modules.get(&module).map_or(AccessMode::Open, |m| m.access);
+    config.client_permitted(module, access, src, &ip)
+}
+
+/// Broadcast destinations, including `#ALL`, are delivered to every peer
+/// on the sender's module.
+fn is_broadcast(dst: &Callsign, reflector_name: &str, module: char) -> bool {
+    match dst {
+        Callsign::Broadcast => true,
+        Callsign::Hash(text) => text.as_str() == "ALL",
+        Callsign::Normal(text) => {
+            text.as_str() == "ALL" || text.as_str() == format!("{} {}", reflector_name, module)
+        }
+        _ => false,
+    }
+}
+
+/// Collects the addresses a frame from `addr` should be forwarded to,
+/// excluding the sender. Frames from a link are only relayed to other
+/// links when `link_transit` is enabled.
+fn target_peers(
+    r: &Reflector,
+    addr: SocketAddr,
+    sender_module: char,
+    src: &Callsign,
+    dst: &Callsign,
+    is_broadcast: bool,
+    link_transit: bool,
+) -> Vec<SocketAddr> {
     let peer_addresses: Vec<SocketAddr> = if is_broadcast {
         if let Some(module) = r.modules.get(&sender_module) {
             module.peers.keys().cloned().collect()
@@ -102,54 +389,21 @@ pub async fn route_stream_packet(
             Vec::new()
         }
     } else {
-        let addrs = r.find_user_peers(&stream.dst);
+        let addrs = r.find_user_peers(dst);
         if addrs.is_empty() {
-            warn!(
-                "Stream from {} to {} dropped: destination unknown",
-                stream.src, stream.dst
-            );
+            warn!("Traffic from {} to {} dropped: destination unknown", src, dst);
         }
         addrs
     };
 
-    let sender_is_link = r.find_peer(&addr).map(|p| p.is_link).unwrap_or(false);
-
-    for peer_addr in peer_addresses {
-        if peer_addr != addr {
-            if sender_is_link {
-                if let Some(p) = r.find_peer(&peer_addr) {
-                    if p.is_link {
-                        continue;
-                    }
-                }
-            }
+    let sender_is_link =
+        !link_transit && r.find_peer(&addr).map(|p| p.is_link).unwrap_or(false);
 
-            if is_broadcast {
-                if let Some(p) = r.find_peer(&peer_addr) {
-                    if p.receiving_unicast.is_some() {
-                        continue;
-                    }
-                }
-            }
-
-            let _ = socket.send_to(data, peer_addr).await;
-
-            if let Some(p) = r.find_peer_mut(&peer_addr) {
-                p.increment_tx(data.len());
-                if !is_broadcast && is_new {
-                    p.receiving_unicast = Some(stream.stream_id);
-                }
-                if !is_broadcast && stream.last_frame {
-                    if p.receiving_unicast == Some(stream.stream_id) {
-                        p.receiving_unicast = None;
-                    }
-                }
-            }
-        }
-    }
-
-    if stream.last_frame {
-        r.end_stream(stream.stream_id);
-        let _ = tx.send("update".into());
-    }
+    peer_addresses
+        .into_iter()
+        .filter(|peer_addr| *peer_addr != addr)
+        .filter(|peer_addr| {
+            !(sender_is_link && r.find_peer(peer_addr).map(|p| p.is_link).unwrap_or(false))
+        })
+        .collect()
 }
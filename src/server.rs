@@ -1,23 +1,36 @@
+use crate::callsign::Callsign;
 use crate::config::Config;
+use crate::dedup::StreamDedup;
+use crate::interlink::{self, resolve, LinkState};
 use crate::reflector::Reflector;
 use crate::packet::{parse_packet, Packet};
 use crate::control::{handle_control_packet, send_ping, send_disc, send_conn};
-use crate::router::route_stream_packet;
+use crate::parrot;
+use crate::ratelimit::{PacketClass, RateLimiter};
+use crate::recorder::Recorder;
+use crate::router::{route_packet_mode, route_stream_packet};
 
 use tokio::net::UdpSocket;
+use std::collections::HashSet;
 use std::sync::Arc;
 use tokio::sync::Mutex;
-use tokio::sync::broadcast;
+use tokio::sync::{broadcast, watch};
 use std::time::{Duration, Instant};
 use log::{info, warn, error};
 
+pub async fn bind(config: &Config) -> std::io::Result<Arc<UdpSocket>> {
+    let socket = UdpSocket::bind(&config.bind_address).await?;
+    info!("Reflector listening on {}", &config.bind_address);
+    Ok(Arc::new(socket))
+}
+
 pub async fn run_with_state(
-    config: &Config,
+    mut config_rx: watch::Receiver<Arc<Config>>,
     reflector: Arc<Mutex<Reflector>>,
+    socket: Arc<UdpSocket>,
     tx: broadcast::Sender<String>,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let socket = Arc::new(UdpSocket::bind(&config.bind_address).await?);
-    info!("Reflector listening on {}", &config.bind_address);
+    let mut config = config_rx.borrow_and_update().clone();
 
     if config.strict_crc {
         info!("CRC enforcement mode: STRICT");
@@ -25,32 +38,87 @@ pub async fn run_with_state(
         info!("CRC enforcement mode: PERMISSIVE");
     }
 
-    for link in &config.interlinks {
-        let addr: std::net::SocketAddr = link.address.parse()?;
-        for module in &link.modules {
-            {
-                let mut r = reflector.lock().await;
-                r.add_link_peer(*module, link.name.clone(), addr);
-            }
-            send_conn(&config.reflector_name, *module, addr, &socket).await?;
-        }
+    reflector.lock().await.dedup = StreamDedup::new(Duration::from_secs(config.loop_window));
+    if config.link_transit {
+        info!("Link-to-link transit: ENABLED");
+    }
+
+    if let Some(path) = &config.ban_file {
+        reflector.lock().await.load_bans(path.clone())?;
+    }
+
+    if let Some(recording) = &config.recording {
+        let recorder = Recorder::new(recording.clone())?;
+        info!("Recording streams to {}", recording.directory.display());
+        reflector.lock().await.recorder = Some(recorder);
+        tokio::spawn(run_recording_rotation_task(Arc::clone(&reflector)));
     }
 
+    let reflector_call: Callsign = config.reflector_name.parse()?;
+
+    let ipv4 = socket.local_addr()?.is_ipv4();
+    let links = interlink::from_config(&config.interlinks, ipv4).await?;
+    reflector.lock().await.sync_interlinks(links);
+
+    tokio::spawn(run_interlink_resolve_task(
+        Arc::clone(&reflector),
+        config_rx.clone(),
+        ipv4,
+    ));
+
+    tokio::spawn(run_interlink_task(
+        Arc::clone(&reflector),
+        Arc::clone(&socket),
+        reflector_call,
+    ));
+
     tokio::spawn(run_keepalive_task(
         Arc::clone(&reflector),
         Arc::clone(&socket),
     ));
-    tokio::spawn(run_stream_timeout_task(Arc::clone(&reflector), tx.clone()));
+    tokio::spawn(run_stream_timeout_task(
+        Arc::clone(&reflector),
+        Arc::clone(&socket),
+        tx.clone(),
+    ));
+    tokio::spawn(run_position_expiry_task(
+        Arc::clone(&reflector),
+        config_rx.clone(),
+    ));
+
+    let stats = Arc::clone(&reflector.lock().await.rate_limit_stats);
+    let mut limiter = RateLimiter::new(config.rate_limit.clone(), stats);
+    let mut next_prune = Instant::now() + Duration::from_secs(10);
 
     let mut buf = [0u8; 1024];
     loop {
         let (len, addr) = socket.recv_from(&mut buf).await?;
         let data = &buf[..len];
+        let now = Instant::now();
+
+        if config_rx.has_changed().unwrap_or(false) {
+            config = config_rx.borrow_and_update().clone();
+            limiter.reconfigure(config.rate_limit.clone());
+        }
+
+        if now >= next_prune {
+            limiter.prune(now);
+            next_prune = now + Duration::from_secs(10);
+        }
 
-        match parse_packet(data) {
+        let packet = parse_packet(data);
+        let class = match packet {
+            Ok(Packet::Stream(_)) | Ok(Packet::PacketMode(_)) => PacketClass::Stream,
+            _ => PacketClass::Control,
+        };
+        if !limiter.check(addr.ip(), class, now) {
+            continue;
+        }
+
+        match packet {
             Ok(Packet::Control(ctrl)) => {
                 let mut r = reflector.lock().await;
-                if let Err(e) = handle_control_packet(ctrl, addr, &mut r, &socket).await {
+                if let Err(e) = handle_control_packet(ctrl, addr, &mut r, &socket, &config).await {
                     error!("Error handling control packet: {}", e);
                 }
                 let _ = tx.send("update".into());
@@ -60,14 +128,17 @@ pub async fn run_with_state(
                     stream,
                     data,
                     addr,
-                    &config.reflector_name,
+                    &config,
                     &reflector,
                     &socket,
-                    config.strict_crc,
                     tx.clone(),
                 )
                 .await;
             }
+            Ok(Packet::PacketMode(packet)) => {
+                route_packet_mode(packet, data, addr, &config, &reflector, &socket, tx.clone())
+                    .await;
+            }
             Err(e) => {
                 warn!("Invalid packet from {}: {:?}", addr, e);
             }
@@ -81,18 +152,23 @@ async fn run_keepalive_task(reflector: Arc<Mutex<Reflector>>, socket: Arc<UdpSoc
             let mut r = reflector.lock().await;
             let now = Instant::now();
 
-            let mut stale_peers = Vec::new();
+            // A link carrying several modules is listed on each of them
+            // but pinged and timed out once.
+            let mut stale_peers = HashSet::new();
             for module in r.modules.values() {
                 for (addr, peer) in module.peers.iter() {
                     if now.duration_since(peer.last_seen) > Duration::from_secs(30) {
-                        stale_peers.push(*addr);
+                        stale_peers.insert(*addr);
                     }
                 }
             }
 
+            let mut pinged = HashSet::new();
             for module in r.modules.values() {
                 for peer in module.peers.values() {
-                    let _ = send_ping(peer, &socket).await;
+                    if pinged.insert(peer.address) {
+                        let _ = send_ping(peer, &socket).await;
+                    }
                 }
             }
 
@@ -103,32 +179,125 @@ async fn run_keepalive_task(reflector: Arc<Mutex<Reflector>>, socket: Arc<UdpSoc
                 }
                 r.remove_peer(&addr);
             }
+
+            r.prune_bans();
         }
         tokio::time::sleep(Duration::from_secs(5)).await;
     }
 }
 
-async fn run_stream_timeout_task(reflector: Arc<Mutex<Reflector>>, tx: broadcast::Sender<String>) {
+/// Keeps every configured interlink connected: sends CONN when a link
+/// is due, notices unanswered CONNs and links whose peer was dropped, and
+/// retries with exponential backoff.
+async fn run_interlink_task(
+    reflector: Arc<Mutex<Reflector>>,
+    socket: Arc<UdpSocket>,
+    reflector_call: Callsign,
+) {
+    loop {
+        {
+            let mut r = reflector.lock().await;
+            let now = Instant::now();
+
+            let lost: Vec<usize> = r
+                .interlinks
+                .iter()
+                .enumerate()
+                .filter(|(_, link)| {
+                    link.state == LinkState::Linked
+                        && link
+                            .address
+                            .is_none_or(|addr| !r.is_attached(&addr, link.module))
+                })
+                .map(|(i, _)| i)
+                .collect();
+            for i in lost {
+                r.interlinks[i].on_lost(now);
+            }
+
+            for link in r.interlinks.iter_mut() {
+                link.check_timeout(now);
+            }
+
+            let due: Vec<usize> = r
+                .interlinks
+                .iter()
+                .enumerate()
+                .filter(|(_, link)| link.connect_due(now))
+                .map(|(i, _)| i)
+                .collect();
+            for i in due {
+                let link = &r.interlinks[i];
+                let (name, module, remote_module) =
+                    (link.name.clone(), link.module, link.remote_module);
+                let Some(addr) = link.address else {
+                    continue;
+                };
+                if !r.add_link_peer(module, name, addr) {
+                    r.interlinks[i].on_address_conflict(now);
+                    continue;
+                }
+                if let Err(e) = send_conn(&reflector_call, remote_module, addr, &socket).await {
+                    error!("Failed to send CONN to {}: {}", addr, e);
+                }
+                r.interlinks[i].on_conn_sent(now);
+            }
+        }
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+}
+
+/// Re-resolves interlink hostnames so links follow remote reflectors
+/// whose address changes.
+async fn run_interlink_resolve_task(
+    reflector: Arc<Mutex<Reflector>>,
+    config_rx: watch::Receiver<Arc<Config>>,
+    ipv4: bool,
+) {
+    loop {
+        let interval = config_rx.borrow().interlink_resolve_interval;
+        tokio::time::sleep(Duration::from_secs(interval)).await;
+
+        let mut hosts: Vec<String> = reflector
+            .lock()
+            .await
+            .interlinks
+            .iter()
+            .map(|l| l.host.clone())
+            .collect();
+        hosts.sort();
+        hosts.dedup();
+        for host in &hosts {
+            match resolve(host, ipv4).await {
+                Ok(addr) => reflector.lock().await.update_link_address(host, addr),
+                Err(e) => warn!("Failed to resolve interlink {}: {}", host, e),
+            }
+        }
+    }
+}
+
+async fn run_stream_timeout_task(
+    reflector: Arc<Mutex<Reflector>>,
+    socket: Arc<UdpSocket>,
+    tx: broadcast::Sender<String>,
+) {
     loop {
         {
             let mut r = reflector.lock().await;
             let now = Instant::now();
             let mut ended = Vec::new();
-            for (id, info) in r.active_streams.iter() {
+            for (key, info) in r.active_streams.iter() {
                 if now.duration_since(info.last_frame) > Duration::from_secs(1) {
-                    ended.push(*id);
+                    ended.push(*key);
                 }
             }
 
-            for &id in &ended {
-                r.end_stream(id);
-                for module in r.modules.values_mut() {
-                    for peer in module.peers.values_mut() {
-                        if peer.receiving_unicast == Some(id) {
-                            peer.receiving_unicast = None;
-                        }
-                    }
-                }
+            for key in &ended {
+                r.end_stream(key);
+            }
+            r.dedup.prune();
+            for recording in r.parrot.take_stale(Duration::from_secs(1)) {
+                tokio::spawn(parrot::replay(recording, Arc::clone(&socket)));
             }
             if !ended.is_empty() {
                 let _ = tx.send("update".into());
@@ -136,4 +305,37 @@ async fn run_stream_timeout_task(reflector: Arc<Mutex<Reflector>>, tx: broadcast
         }
         tokio::time::sleep(Duration::from_millis(200)).await;
     }
-}
\ No newline at end of file
+}
+
+async fn run_position_expiry_task(
+    reflector: Arc<Mutex<Reflector>>,
+    config_rx: watch::Receiver<Arc<Config>>,
+) {
+    loop {
+        {
+            let max_age = Duration::from_secs(config_rx.borrow().position_max_age);
+            let mut r = reflector.lock().await;
+            r.prune_positions(max_age);
+        }
+        tokio::time::sleep(Duration::from_secs(10)).await;
+    }
+}
+
+async fn run_recording_rotation_task(reflector: Arc<Mutex<Reflector>>) {
+    loop {
+        let config = reflector
+            .lock()
+            .await
+            .recorder
+            .as_ref()
+            .map(|recorder| recorder.config().clone());
+        if let Some(config) = config {
+            match tokio::task::spawn_blocking(move || Recorder::rotate(&config)).await {
+                Ok(Err(e)) => error!("Failed to rotate recordings: {}", e),
+                Err(e) => error!("Recording rotation failed: {}", e),
+                Ok(Ok(())) => {}
+            }
+        }
+        tokio::time::sleep(Duration::from_secs(60)).await;
+    }
+}
@@ -0,0 +1,43 @@
+use crate::control::send_disc;
+use crate::reflector::Reflector;
+use log::{error, info};
+use tokio::net::UdpSocket;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::Mutex;
+
+/// Resolves once Ctrl-C or SIGTERM is received.
+pub async fn wait_for_signal() {
+    let mut terminate = match signal(SignalKind::terminate()) {
+        Ok(terminate) => Some(terminate),
+        Err(e) => {
+            error!("Failed to listen for SIGTERM: {}", e);
+            None
+        }
+    };
+
+    tokio::select! {
+        Ok(()) = tokio::signal::ctrl_c() => info!("Ctrl-C received, shutting down"),
+        Some(_) = async { terminate.as_mut()?.recv().await } => {
+            info!("SIGTERM received, shutting down");
+        }
+        else => {
+            error!("No shutdown signal can be received");
+            std::future::pending::<()>().await
+        }
+    }
+}
+
+/// Ends all streams, flushing their recordings, and sends DISC to every
+/// peer and interlink.
+pub async fn disconnect_all(reflector: &Mutex<Reflector>, socket: &UdpSocket) {
+    let mut r = reflector.lock().await;
+    let peers = r.disconnect_all();
+    // Dropping the recorder waits for its writer to flush the files.
+    r.recorder = None;
+    for peer in &peers {
+        if let Err(e) = send_disc(peer, socket).await {
+            error!("Failed to send DISC to {}: {}", peer.address, e);
+        }
+    }
+    info!("Sent DISC to {} peers", peers.len());
+}
@@ -1,3 +1,9 @@
+use crate::config::AccessMode;
+use crate::interlink::LinkState;
+use crate::lsf::{DataType, EncryptionType};
+use crate::meta::GnssPosition;
+use crate::quality::StreamQuality;
+use crate::ratelimit::RateLimitInfo;
 use serde::Serialize;
 use std::collections::HashMap;
 use std::time::{Instant, SystemTime};
@@ -10,6 +16,8 @@ pub struct ClientInfo {
     pub last_seen: SystemTime,
     pub packets_in: u64,
     pub bytes_in: u64,
+    pub is_link: bool,
+    pub muted: bool,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -20,10 +28,54 @@ pub struct StreamInfo {
     pub module: char,
     pub stream_id: u16,
     pub frames: u32,
+    pub data_type: DataType,
+    pub encryption: EncryptionType,
+    pub can: u8,
+    pub text: Option<String>,
+    pub quality: StreamQuality,
     pub started_at: SystemTime,
     pub ended_at: Option<SystemTime>,
 }
 
+#[derive(Debug, Clone, Serialize)]
+pub struct PositionInfo {
+    pub callsign: String,
+    pub module: char,
+    #[serde(flatten)]
+    pub position: GnssPosition,
+    pub updated_at: SystemTime,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordingInfo {
+    pub name: String,
+    pub size: u64,
+    pub modified: SystemTime,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InterlinkInfo {
+    pub name: String,
+    pub host: String,
+    pub address: Option<String>,
+    pub module: char,
+    pub remote_module: char,
+    pub state: LinkState,
+    pub attempts: u32,
+    pub state_since: SystemTime,
+}
+
+/// Configured settings and counters of a module.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ModuleInfo {
+    pub description: String,
+    pub access: AccessMode,
+    pub talk_timeout: Option<u64>,
+    pub max_clients: Option<usize>,
+    pub linked: bool,
+    pub refused_connections: u64,
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct ModuleStats {
     pub module: char,
@@ -31,6 +83,8 @@ pub struct ModuleStats {
     pub active_streams: usize,
     pub total_packets: u64,
     pub total_bytes: u64,
+    #[serde(flatten)]
+    pub info: ModuleInfo,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -45,6 +99,10 @@ pub struct StatsSnapshot {
     pub clients: Vec<ClientInfo>,
     pub active_streams: Vec<StreamInfo>,
     pub recent_streams: Vec<StreamInfo>,
+    pub positions: Vec<PositionInfo>,
+    pub interlinks: Vec<InterlinkInfo>,
+    pub rate_limit: RateLimitInfo,
+    pub ignored_control_packets: u64,
 }
 
 pub struct ReflectorState {
@@ -53,6 +111,17 @@ pub struct ReflectorState {
     pub clients: HashMap<String, ClientInfo>,
     pub active_streams: Vec<StreamInfo>,
     pub recent_streams: Vec<StreamInfo>,
+    pub positions: Vec<PositionInfo>,
+    pub interlinks: Vec<InterlinkInfo>,
+    pub rate_limit: RateLimitInfo,
+    pub ignored_control_packets: u64,
+    pub modules: HashMap<char, ModuleInfo>,
+}
+
+impl Default for ReflectorState {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ReflectorState {
@@ -63,13 +132,22 @@ impl ReflectorState {
             clients: HashMap::new(),
             active_streams: Vec::new(),
             recent_streams: Vec::new(),
+            positions: Vec::new(),
+            interlinks: Vec::new(),
+            rate_limit: RateLimitInfo::default(),
+            ignored_control_packets: 0,
+            modules: HashMap::new(),
         }
     }
 
     pub fn snapshot(&self) -> StatsSnapshot {
         let uptime_seconds = self.start_time.elapsed().as_secs();
 
-        let mut module_map: HashMap<char, (usize, usize, u64, u64)> = HashMap::new();
+        let mut module_map: HashMap<char, (usize, usize, u64, u64)> = self
+            .modules
+            .keys()
+            .map(|module| (*module, (0, 0, 0, 0)))
+            .collect();
 
         for client in self.clients.values() {
             let entry = module_map.entry(client.module).or_insert((0, 0, 0, 0));
@@ -86,7 +164,7 @@ impl ReflectorState {
             }
         }
 
-        let module_stats: Vec<ModuleStats> = module_map
+        let mut module_stats: Vec<ModuleStats> = module_map
             .into_iter()
             .map(
                 |(module, (clients, active_streams, total_packets, total_bytes))| ModuleStats {
@@ -95,9 +173,11 @@ impl ReflectorState {
                     active_streams,
                     total_packets,
                     total_bytes,
+                    info: self.modules.get(&module).cloned().unwrap_or_default(),
                 },
             )
             .collect();
+        module_stats.sort_by_key(|m| m.module);
 
         let (total_packets, total_bytes) = module_stats.iter().fold((0u64, 0u64), |acc, m| {
             (acc.0 + m.total_packets, acc.1 + m.total_bytes)
@@ -114,6 +194,10 @@ impl ReflectorState {
             clients: self.clients.values().cloned().collect(),
             active_streams: self.active_streams.clone(),
             recent_streams: self.recent_streams.clone(),
+            positions: self.positions.clone(),
+            interlinks: self.interlinks.clone(),
+            rate_limit: self.rate_limit.clone(),
+            ignored_control_packets: self.ignored_control_packets,
         }
     }
 }
@@ -24,7 +24,7 @@ async fn handle_socket(
         return;
     }
 
-    while let Ok(_) = rx.recv().await {
+    while rx.recv().await.is_ok() {
         if send_snapshot(&mut socket, &state).await.is_err() {
             break;
         }
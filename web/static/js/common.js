@@ -41,6 +41,12 @@ function updateTable(id, arr, mapFn) {
     });
 }
 
+function escapeHtml(text) {
+    const div = document.createElement('div');
+    div.textContent = text;
+    return div.innerHTML;
+}
+
 function formatTime(ts) {
     if (!ts) return '';
     const d = new Date(ts.secs_since_epoch * 1000);
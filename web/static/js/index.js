@@ -16,11 +16,13 @@ document.addEventListener("reflectorUpdate", (e) => {
     ]);
 
     updateTable('activeStreamsTable', s.active_streams, a => [
-        a.source,
+        formatSource(a),
         a.peer || '-',
         a.destination,
         a.module,
         a.stream_id,
+        formatMode(a),
+        a.can,
         formatDuration(Math.floor(Date.now() / 1000) - a.started_at.secs_since_epoch)
     ]);
 
@@ -28,7 +30,7 @@ document.addEventListener("reflectorUpdate", (e) => {
         'recentStreamsTable',
         s.recent_streams.sort((a, b) => b.ended_at.secs_since_epoch - a.ended_at.secs_since_epoch),
         r => [
-            r.source,
+            formatSource(r),
             r.peer || '-',
             r.destination,
             r.module,
@@ -36,3 +38,22 @@ document.addEventListener("reflectorUpdate", (e) => {
         ]
     );
 });
+
+function formatSource(stream) {
+    if (!stream.text) return stream.source;
+    return `${stream.source}<br><small>${escapeHtml(stream.text)}</small>`;
+}
+
+function formatMode(stream) {
+    const modes = {
+        voice: 'Voice (3200)',
+        voice_data: 'Voice+Data (1600)',
+        data: 'Data',
+        reserved: 'Reserved'
+    };
+    const mode = modes[stream.data_type] || stream.data_type;
+    if (stream.encryption && stream.encryption !== 'none') {
+        return `${mode} <span class="tag is-warning">${stream.encryption}</span>`;
+    }
+    return mode;
+}
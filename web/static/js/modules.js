@@ -37,6 +37,7 @@ document.addEventListener("reflectorUpdate", (e) => {
         moduleBox.innerHTML = `
       <div class="module-header">
         <h2 class="subtitle">Module ${mod.module} (Peers: ${mod.clients})</h2>
+        ${mod.description ? `<p>${escapeHtml(mod.description)}</p>` : ''}
         ${moduleStatusTag}
       </div>
     `;